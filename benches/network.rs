@@ -0,0 +1,124 @@
+//! Throughput of training and predicting, run with `cargo bench`.
+
+use std::sync::{atomic::AtomicBool, Arc};
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use mnist_ai_rust::{
+	checkpoint::TrainingState,
+	dataset::Dataset,
+	model::{ModelFile, ModelFormat},
+	network::Network,
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// The shape of a new network with the default hidden layers.
+const SHAPE: [usize; 4] = [784, 16, 16, 10];
+const BATCH_SIZES: [usize; 4] = [1, 8, 32, 128];
+/// The amount of images in the dataset of a full dataset iteration.
+const EPOCH_SAMPLES: usize = 1000;
+
+fn network(shape: &[usize]) -> Network {
+	let mut rng = ChaCha8Rng::seed_from_u64(0);
+	Network::with_rng(0.1, shape, &mut rng).unwrap()
+}
+
+fn inputs(amount: usize) -> (Vec<Vec<f32>>, Vec<u8>) {
+	let mut rng = ChaCha8Rng::seed_from_u64(1);
+	let inputs = (0..amount)
+		.map(|_| (0..SHAPE[0]).map(|_| rng.gen()).collect())
+		.collect();
+	let labels = (0..amount).map(|_| rng.gen_range(0..10)).collect();
+	(inputs, labels)
+}
+
+fn forward(c: &mut Criterion) {
+	let mut network = network(&SHAPE);
+	let (inputs, _) = inputs(1);
+
+	let mut group = c.benchmark_group("forward");
+	group.throughput(Throughput::Elements(1));
+	group.bench_function("feed_forward", |b| {
+		b.iter(|| network.feed_forward(black_box(inputs[0].clone())))
+	});
+	group.bench_function("infer", |b| b.iter(|| network.infer(black_box(&inputs[0]))));
+	group.finish();
+}
+
+fn backward(c: &mut Criterion) {
+	let mut network = network(&SHAPE);
+	let (inputs, labels) = inputs(1);
+	let activations = network.forward(&inputs[0]);
+
+	let mut group = c.benchmark_group("backward");
+	group.throughput(Throughput::Elements(1));
+	group.bench_function("gradients", |b| {
+		b.iter(|| network.gradients_from(black_box(&activations), labels[0]))
+	});
+	group.bench_function("feed_forward_and_backpropagate", |b| {
+		b.iter(|| {
+			network.feed_forward(black_box(inputs[0].clone()));
+			network.backpropagate(labels[0]);
+		})
+	});
+	group.finish();
+}
+
+fn mini_batch(c: &mut Criterion) {
+	let mut network = network(&SHAPE);
+
+	let mut group = c.benchmark_group("mini_batch");
+	for batch_size in BATCH_SIZES {
+		let (inputs, labels) = inputs(batch_size);
+		group.throughput(Throughput::Elements(batch_size as u64));
+		group.bench_function(batch_size.to_string(), |b| {
+			b.iter(|| network.train_batch(black_box(&inputs), &labels))
+		});
+	}
+	group.finish();
+}
+
+fn epoch(c: &mut Criterion) {
+	let mut rng = ChaCha8Rng::seed_from_u64(2);
+	let dataset = Arc::new(Dataset::digits(EPOCH_SAMPLES, 0, &mut rng));
+	let mut network = network(&SHAPE);
+	network.dataset = Some(dataset);
+
+	let mut group = c.benchmark_group("epoch");
+	group.sample_size(10);
+	group.throughput(Throughput::Elements(EPOCH_SAMPLES as u64));
+	group.bench_function("digits", |b| {
+		b.iter_batched(
+			|| {
+				// Start after the first dataset iteration, which doesn't back propagate.
+				let mut state = TrainingState::new(2, true, 0);
+				state.iteration = 1;
+				state
+			},
+			|mut state| network.train_from(&mut state, None, &mut [], &AtomicBool::new(false)),
+			BatchSize::SmallInput,
+		)
+	});
+	group.finish();
+}
+
+fn save_and_load(c: &mut Criterion) {
+	let model = network(&[784, 128, 64, 10]).to_model();
+
+	let mut group = c.benchmark_group("model");
+	for file in ["model.json", "model.bin", "model.f16.bin", "model.bin.gz"] {
+		let format = ModelFormat::from_path(file);
+		let encoded = model.encode(format).unwrap();
+
+		group.bench_function(format!("save/{}", file), |b| {
+			b.iter(|| model.encode(black_box(format)).unwrap())
+		});
+		group.bench_function(format!("load/{}", file), |b| {
+			b.iter(|| ModelFile::decode(black_box(&encoded)).unwrap())
+		});
+	}
+	group.finish();
+}
+
+criterion_group!(benches, forward, backward, mini_batch, epoch, save_and_load);
+criterion_main!(benches);
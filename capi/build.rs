@@ -0,0 +1,15 @@
+use std::{env, path::Path};
+
+/// Generate the C header from the exported functions into `OUT_DIR`.
+/// The copy in `include` is checked against it by the tests, instead of being overwritten.
+fn main() {
+	let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+	let out_dir = env::var("OUT_DIR").unwrap();
+
+	cbindgen::generate(&crate_dir)
+		.expect("Unable to generate the C header")
+		.write_to_file(Path::new(&out_dir).join("mnist_ai.h"));
+
+	println!("cargo:rerun-if-changed=src/lib.rs");
+	println!("cargo:rerun-if-changed=cbindgen.toml");
+}
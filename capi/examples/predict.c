@@ -0,0 +1,35 @@
+/* Predict the digit of an empty image with a saved model.
+ *
+ *   cargo build -p mnist-ai-capi --release
+ *   cc capi/examples/predict.c -Icapi/include -Ltarget/release -lmnist_ai -o predict
+ *   LD_LIBRARY_PATH=target/release ./predict networks/network_low_lr.json
+ */
+#include <stdio.h>
+
+#include "mnist_ai.h"
+
+int main(int argc, char **argv) {
+	if (argc < 2) {
+		fprintf(stderr, "usage: %s <model>\n", argv[0]);
+		return 1;
+	}
+
+	MnistModel *model = mnist_model_load(argv[1]);
+	if (model == NULL) {
+		fprintf(stderr, "could not load model: %s\n", mnist_last_error());
+		return 1;
+	}
+
+	float pixels[784] = {0};
+	float probabilities[10];
+	int digit = mnist_predict(model, pixels, 784, probabilities, 10);
+	if (digit < 0) {
+		fprintf(stderr, "could not predict: %s\n", mnist_last_error());
+		mnist_model_free(model);
+		return 1;
+	}
+
+	printf("digit %d (%.2f%%)\n", digit, probabilities[digit] * 100.0f);
+	mnist_model_free(model);
+	return 0;
+}
@@ -0,0 +1,79 @@
+#ifndef MNIST_AI_H
+#define MNIST_AI_H
+
+/* This file is generated by cbindgen from capi/src/lib.rs, don't edit it by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The version of this API, increased whenever a function changes incompatibly.
+#define MNIST_ABI_VERSION 1
+
+// A network loaded from a saved model. Only used through pointers.
+typedef struct MnistModel MnistModel;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// The version of the API the library implements, see `MNIST_ABI_VERSION`.
+uint32_t mnist_abi_version(void);
+
+// Load a model saved in any format from a nul terminated path.
+// Returns NULL on failure.
+//
+// # Safety
+// `path` must be NULL or point to a nul terminated string.
+struct MnistModel *mnist_model_load(const char *path);
+
+// Load a model saved in any format from `len` bytes of memory.
+// The bytes are copied, so the buffer can be freed afterwards. Returns NULL on failure.
+//
+// # Safety
+// `data` must be NULL or point to at least `len` readable bytes.
+struct MnistModel *mnist_model_load_buffer(const uint8_t *data, size_t len);
+
+// The amount of inputs the model expects, 784 for MNIST images. Returns 0 if `model` is NULL.
+//
+// # Safety
+// `model` must be NULL or a pointer returned by one of the load functions.
+size_t mnist_model_input_size(const struct MnistModel *model);
+
+// The amount of outputs, and so probabilities, of the model. Returns 0 if `model` is NULL.
+//
+// # Safety
+// `model` must be NULL or a pointer returned by one of the load functions.
+size_t mnist_model_output_size(const struct MnistModel *model);
+
+// Predict the digit of `input_len` pixels between 0 and 1, a light digit on a dark background.
+// If `probabilities` isn't NULL, the probability of every digit is written to it.
+// Returns the predicted digit, or -1 on failure.
+//
+// # Safety
+// `model` must be a pointer returned by one of the load functions, `input` must point to
+// `input_len` floats and `probabilities` must be NULL or point to `probabilities_len` floats.
+int32_t mnist_predict(const struct MnistModel *model,
+                      const float *input,
+                      size_t input_len,
+                      float *probabilities,
+                      size_t probabilities_len);
+
+// Free a model. Passing NULL does nothing.
+//
+// # Safety
+// `model` must be NULL or a pointer returned by one of the load functions,
+// which hasn't been freed yet.
+void mnist_model_free(struct MnistModel *model);
+
+// A description of the last error on the calling thread, or NULL if nothing failed yet.
+// The string is owned by the library and valid until the next failing call on the thread.
+const char *mnist_last_error(void);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* MNIST_AI_H */
@@ -0,0 +1,333 @@
+//! C API for classifying digits with a trained network.
+//!
+//! Every function catches panics and reports failures through its return value,
+//! with a description available from `mnist_last_error`.
+
+use std::{
+	cell::RefCell,
+	ffi::{c_char, CStr, CString},
+	panic::{catch_unwind, AssertUnwindSafe},
+	ptr, slice,
+};
+
+use mnist_ai_rust::{model::ModelFile, network::Network};
+
+/// The version of this API, increased whenever a function changes incompatibly.
+pub const MNIST_ABI_VERSION: u32 = 1;
+
+/// A network loaded from a saved model. Only used through pointers.
+pub struct MnistModel {
+	network: Network,
+}
+
+thread_local! {
+	static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Remember an error for `mnist_last_error` on this thread.
+fn set_error(error: impl ToString) {
+	// Interior nul bytes would cut the message short, so they are replaced.
+	let message = error.to_string().replace('\0', " ");
+	let message = CString::new(message).expect("Nul bytes were removed");
+	LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Run `f`, turning errors and panics into `fallback` and a stored error.
+fn guard<T>(fallback: T, f: impl FnOnce() -> Result<T, String>) -> T {
+	match catch_unwind(AssertUnwindSafe(f)) {
+		Ok(Ok(value)) => value,
+		Ok(Err(error)) => {
+			set_error(error);
+			fallback
+		}
+		Err(_) => {
+			set_error("unexpected panic");
+			fallback
+		}
+	}
+}
+
+fn into_model(model: ModelFile) -> Result<*mut MnistModel, String> {
+	let network = Network::from_model(model).map_err(|err| err.to_string())?;
+	Ok(Box::into_raw(Box::new(MnistModel { network })))
+}
+
+/// The version of the API the library implements, see `MNIST_ABI_VERSION`.
+#[no_mangle]
+pub extern "C" fn mnist_abi_version() -> u32 {
+	MNIST_ABI_VERSION
+}
+
+/// Load a model saved in any format from a nul terminated path.
+/// Returns NULL on failure.
+///
+/// # Safety
+/// `path` must be NULL or point to a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mnist_model_load(path: *const c_char) -> *mut MnistModel {
+	guard(ptr::null_mut(), || {
+		if path.is_null() {
+			return Err("path is NULL".to_string());
+		}
+
+		let path = CStr::from_ptr(path)
+			.to_str()
+			.map_err(|_| "path is not valid UTF-8".to_string())?;
+		into_model(ModelFile::read(path).map_err(|err| err.to_string())?)
+	})
+}
+
+/// Load a model saved in any format from `len` bytes of memory.
+/// The bytes are copied, so the buffer can be freed afterwards. Returns NULL on failure.
+///
+/// # Safety
+/// `data` must be NULL or point to at least `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn mnist_model_load_buffer(data: *const u8, len: usize) -> *mut MnistModel {
+	guard(ptr::null_mut(), || {
+		if data.is_null() {
+			return Err("data is NULL".to_string());
+		}
+
+		let bytes = slice::from_raw_parts(data, len);
+		into_model(ModelFile::decode(bytes).map_err(|err| err.to_string())?)
+	})
+}
+
+/// The amount of inputs the model expects, 784 for MNIST images. Returns 0 if `model` is NULL.
+///
+/// # Safety
+/// `model` must be NULL or a pointer returned by one of the load functions.
+#[no_mangle]
+pub unsafe extern "C" fn mnist_model_input_size(model: *const MnistModel) -> usize {
+	model.as_ref().map_or(0, |model| model.network.shape()[0])
+}
+
+/// The amount of outputs, and so probabilities, of the model. Returns 0 if `model` is NULL.
+///
+/// # Safety
+/// `model` must be NULL or a pointer returned by one of the load functions.
+#[no_mangle]
+pub unsafe extern "C" fn mnist_model_output_size(model: *const MnistModel) -> usize {
+	model
+		.as_ref()
+		.map_or(0, |model| model.network.output_layer.neurons.len())
+}
+
+/// Predict the digit of `input_len` pixels between 0 and 1, a light digit on a dark background.
+/// If `probabilities` isn't NULL, the probability of every digit is written to it.
+/// Returns the predicted digit, or -1 on failure.
+///
+/// # Safety
+/// `model` must be a pointer returned by one of the load functions, `input` must point to
+/// `input_len` floats and `probabilities` must be NULL or point to `probabilities_len` floats.
+#[no_mangle]
+pub unsafe extern "C" fn mnist_predict(
+	model: *const MnistModel,
+	input: *const f32,
+	input_len: usize,
+	probabilities: *mut f32,
+	probabilities_len: usize,
+) -> i32 {
+	guard(-1, || {
+		let model = model.as_ref().ok_or("model is NULL")?;
+		if input.is_null() {
+			return Err("input is NULL".to_string());
+		}
+
+		let input_size = model.network.shape()[0];
+		if input_len != input_size {
+			return Err(format!("expected {} inputs, got {}", input_size, input_len));
+		}
+
+		let prediction = model
+			.network
+			.predict(slice::from_raw_parts(input, input_len));
+
+		if !probabilities.is_null() {
+			if probabilities_len < prediction.probabilities.len() {
+				return Err(format!(
+					"probabilities needs room for {} values, got {}",
+					prediction.probabilities.len(),
+					probabilities_len
+				));
+			}
+
+			let output = slice::from_raw_parts_mut(probabilities, prediction.probabilities.len());
+			output.copy_from_slice(&prediction.probabilities);
+		}
+
+		Ok(prediction.digit as i32)
+	})
+}
+
+/// Free a model. Passing NULL does nothing.
+///
+/// # Safety
+/// `model` must be NULL or a pointer returned by one of the load functions,
+/// which hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn mnist_model_free(model: *mut MnistModel) {
+	if !model.is_null() {
+		drop(Box::from_raw(model));
+	}
+}
+
+/// A description of the last error on the calling thread, or NULL if nothing failed yet.
+/// The string is owned by the library and valid until the next failing call on the thread.
+#[no_mangle]
+pub extern "C" fn mnist_last_error() -> *const c_char {
+	LAST_ERROR.with(|last| {
+		last.borrow()
+			.as_ref()
+			.map_or(ptr::null(), |message| message.as_ptr())
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use mnist_ai_rust::model::ModelFormat;
+
+	use super::*;
+
+	fn model_bytes(file: &str) -> Vec<u8> {
+		Network::new(0.1, &[4, 3, 10])
+			.unwrap()
+			.to_model()
+			.encode(ModelFormat::from_path(file))
+			.unwrap()
+	}
+
+	fn load(bytes: &[u8]) -> *mut MnistModel {
+		unsafe { mnist_model_load_buffer(bytes.as_ptr(), bytes.len()) }
+	}
+
+	fn last_error() -> String {
+		let error = mnist_last_error();
+		assert!(!error.is_null());
+		unsafe { CStr::from_ptr(error) }
+			.to_str()
+			.unwrap()
+			.to_string()
+	}
+
+	#[test]
+	fn loads_from_a_buffer_and_predicts() {
+		for file in ["model.json", "model.f16.bin.gz"] {
+			let model = load(&model_bytes(file));
+			assert!(!model.is_null(), "{}: {}", file, last_error());
+
+			unsafe {
+				assert_eq!(mnist_model_input_size(model), 4);
+				assert_eq!(mnist_model_output_size(model), 10);
+
+				let input = [0.5; 4];
+				let mut probabilities = [0.; 10];
+				let digit = mnist_predict(
+					model,
+					input.as_ptr(),
+					input.len(),
+					probabilities.as_mut_ptr(),
+					probabilities.len(),
+				);
+				assert!((0..10).contains(&digit));
+				assert!((probabilities.iter().sum::<f32>() - 1.).abs() < 1e-5);
+				let most_likely = probabilities
+					.iter()
+					.enumerate()
+					.max_by(|(_, a), (_, b)| a.total_cmp(b))
+					.unwrap()
+					.0;
+				assert_eq!(digit, most_likely as i32);
+
+				// The probabilities are optional.
+				let again = mnist_predict(model, input.as_ptr(), input.len(), ptr::null_mut(), 0);
+				assert_eq!(again, digit);
+
+				mnist_model_free(model);
+			}
+		}
+	}
+
+	#[test]
+	fn rejects_invalid_buffers() {
+		assert!(load(b"not a model").is_null());
+		assert!(!last_error().is_empty());
+
+		assert!(unsafe { mnist_model_load_buffer(ptr::null(), 3) }.is_null());
+		assert_eq!(last_error(), "data is NULL");
+
+		assert!(unsafe { mnist_model_load(ptr::null()) }.is_null());
+		assert_eq!(last_error(), "path is NULL");
+	}
+
+	#[test]
+	fn rejects_the_wrong_input_size() {
+		let model = load(&model_bytes("model.json"));
+		let input = [0.5; 5];
+
+		unsafe {
+			let digit = mnist_predict(model, input.as_ptr(), input.len(), ptr::null_mut(), 0);
+			assert_eq!(digit, -1);
+			assert_eq!(last_error(), "expected 4 inputs, got 5");
+			mnist_model_free(model);
+		}
+	}
+
+	#[test]
+	fn rejects_small_probability_buffers() {
+		let model = load(&model_bytes("model.json"));
+		let input = [0.5; 4];
+		let mut probabilities = [-1.; 10];
+
+		unsafe {
+			let digit = mnist_predict(
+				model,
+				input.as_ptr(),
+				input.len(),
+				probabilities.as_mut_ptr(),
+				9,
+			);
+			assert_eq!(digit, -1);
+			assert_eq!(
+				last_error(),
+				"probabilities needs room for 10 values, got 9"
+			);
+			// Nothing is written to a buffer that is too small.
+			assert_eq!(probabilities, [-1.; 10]);
+			mnist_model_free(model);
+		}
+	}
+
+	#[test]
+	fn handles_null_models() {
+		let input = [0.5; 4];
+
+		unsafe {
+			assert_eq!(mnist_model_input_size(ptr::null()), 0);
+			assert_eq!(mnist_model_output_size(ptr::null()), 0);
+			let digit = mnist_predict(ptr::null(), input.as_ptr(), 4, ptr::null_mut(), 0);
+			assert_eq!(digit, -1);
+			assert_eq!(last_error(), "model is NULL");
+			mnist_model_free(ptr::null_mut());
+		}
+	}
+
+	#[test]
+	fn errors_are_kept_per_thread() {
+		std::thread::spawn(|| assert!(mnist_last_error().is_null()))
+			.join()
+			.unwrap();
+	}
+
+	#[test]
+	fn the_header_is_up_to_date() {
+		let generated = include_str!(concat!(env!("OUT_DIR"), "/mnist_ai.h"));
+		let committed = include_str!("../include/mnist_ai.h");
+		assert!(
+			generated == committed,
+			"include/mnist_ai.h is out of date, copy it from {}",
+			concat!(env!("OUT_DIR"), "/mnist_ai.h")
+		);
+	}
+}
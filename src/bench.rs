@@ -0,0 +1,65 @@
+use std::time::{Duration, Instant};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{error::Result, network::Network};
+
+/// How many images per second a network gets through.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Throughput {
+	/// Feeding images forward, without training.
+	pub forward: f64,
+	/// Feeding images forward and back propagating them one at a time.
+	pub train: f64,
+	/// Training on mini-batches.
+	pub batch_train: f64,
+}
+
+/// Measure the throughput of a network of `shape` on `samples` random images,
+/// using mini-batches of `batch_size` images for `batch_train`.
+pub fn measure(shape: &[usize], samples: usize, batch_size: usize) -> Result<Throughput> {
+	let mut rng = ChaCha8Rng::seed_from_u64(0);
+	let mut network = Network::with_rng(0.1, shape, &mut rng)?;
+
+	let outputs = shape[shape.len() - 1];
+	let inputs: Vec<Vec<f32>> = (0..samples)
+		.map(|_| (0..shape[0]).map(|_| rng.gen()).collect())
+		.collect();
+	let labels: Vec<u8> = (0..samples)
+		.map(|_| rng.gen_range(0..outputs) as u8)
+		.collect();
+
+	let forward = time(|| {
+		for input in inputs.iter() {
+			network.feed_forward(input.clone());
+		}
+	});
+
+	let train = time(|| {
+		for (input, label) in inputs.iter().zip(&labels) {
+			network.feed_forward(input.clone());
+			network.backpropagate(*label);
+		}
+	});
+
+	let batch_train = time(|| {
+		let batches = inputs.chunks(batch_size.max(1));
+		for (batch, batch_labels) in batches.zip(labels.chunks(batch_size.max(1))) {
+			network.train_batch(batch, batch_labels);
+		}
+	});
+
+	let per_second = |duration: Duration| samples as f64 / duration.as_secs_f64();
+	Ok(Throughput {
+		forward: per_second(forward),
+		train: per_second(train),
+		batch_train: per_second(batch_train),
+	})
+}
+
+fn time(f: impl FnOnce()) -> Duration {
+	let start = Instant::now();
+	f();
+	start.elapsed()
+}
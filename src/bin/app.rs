@@ -1,5 +1,13 @@
-use clap::{command, Parser};
-use mnist_ai_rust::network::Network;
+use std::sync::OnceLock;
+
+use clap::Parser;
+use log::error;
+use mnist_ai_rust::{
+	logging::{self, LoggingOptions},
+	network::Network,
+	predict::IMAGE_SIZE,
+	preprocessing::{from_pixels, Preprocessing},
+};
 use nannou::prelude::*;
 use nannou_egui::{egui, Egui};
 
@@ -8,10 +16,19 @@ use nannou_egui::{egui, Egui};
 struct Args {
 	#[arg(short, long, default_value = None)]
 	input: String,
+	#[command(flatten)]
+	logging: LoggingOptions,
 }
 
-fn main() {
+/// The parsed arguments, nannou only accepts a plain function to build the model.
+static ARGS: OnceLock<Args> = OnceLock::new();
+
+fn main() -> anyhow::Result<()> {
+	let args = ARGS.get_or_init(Args::parse);
+	logging::init(&args.logging)?;
+
 	nannou::app(model).update(update).run();
+	Ok(())
 }
 
 struct Model {
@@ -31,6 +48,8 @@ const CELL_SIZE: f32 = 28.;
 
 struct Grid {
 	cells: Vec<Vec<Cell>>,
+	/// Whether the cells show a drawing rather than an MNIST image.
+	drawing: bool,
 }
 
 #[derive(Clone)]
@@ -41,6 +60,7 @@ struct Cell {
 
 impl Grid {
 	fn on_click(&mut self, mouse_pos: Point2) {
+		self.drawing = true;
 		let cells = self.cells.iter_mut().flatten();
 
 		for cell in cells {
@@ -74,15 +94,17 @@ fn model(app: &App) -> Model {
 		.unwrap();
 	let window = app.window(window_id).unwrap();
 
-	let args = Args::parse();
+	let args = ARGS
+		.get()
+		.expect("arguments are parsed before the app starts");
 
-	let mut network = Network::new(0.1, (784, 16, 16, 10));
-	network.load_layers(args.input).unwrap();
+	let network = Network::from_file(&args.input).unwrap();
 
 	let egui = Egui::from_window(&window);
 
 	let mut grid = Grid {
 		cells: Vec::with_capacity(COLS as usize),
+		drawing: true,
 	};
 
 	for col in 0..COLS as usize {
@@ -94,7 +116,7 @@ fn model(app: &App) -> Model {
 			let grid_h = ROWS * CELL_SIZE;
 
 			let x = (row as f32 * CELL_SIZE) - (grid_w / 2.) + (CELL_SIZE / 2.);
-			let y = ((COLS - col as f32) as f32 * CELL_SIZE) - (grid_h / 2.) + (CELL_SIZE / 2.);
+			let y = ((COLS - col as f32) * CELL_SIZE) - (grid_h / 2.) + (CELL_SIZE / 2.);
 
 			col_row.push(Cell {
 				position: Point2::new(x, y),
@@ -126,13 +148,20 @@ fn update(app: &App, model: &mut Model, update: Update) {
 		grid.on_click(mouse);
 	}
 
-	network.feed_forward(
-		grid.cells
-			.iter()
-			.flatten()
-			.map(|cell| cell.activation)
-			.collect(),
-	);
+	let pixels = grid
+		.cells
+		.iter()
+		.flatten()
+		.map(|cell| cell.activation.min(1.))
+		.collect();
+	// Make a drawing look like an MNIST image before it's fed forward,
+	// the MNIST images already are.
+	if grid.drawing {
+		let image = from_pixels(pixels, IMAGE_SIZE, IMAGE_SIZE).expect("The grid is 28×28 cells");
+		network.feed_forward(Preprocessing::default().apply(&image));
+	} else {
+		network.feed_forward(pixels);
+	}
 
 	egui.set_elapsed_time(update.since_start);
 	let ctx = egui.begin_frame();
@@ -168,8 +197,15 @@ fn update(app: &App, model: &mut Model, update: Update) {
 					return;
 				}
 
-				let images = network.test_images.clone();
-				let labels = network.test_labels.clone();
+				let dataset = match network.dataset() {
+					Ok(dataset) => dataset,
+					Err(err) => {
+						error!(target: "console", "{}", err);
+						return;
+					}
+				};
+				let images = &dataset.test_images;
+				let labels = &dataset.test_labels;
 				let outer_images = images.outer_iter();
 				let outer_labels = labels.outer_iter();
 				let image = outer_images
@@ -178,13 +214,14 @@ fn update(app: &App, model: &mut Model, update: Update) {
 					.unwrap();
 
 				*loaded_label = *image.1.get(0).unwrap();
+				grid.drawing = false;
 
 				for (cell, pixel) in grid.cells.iter_mut().flatten().zip(image.0) {
 					cell.activation = *pixel;
 				}
 			}
 
-			ui.label(format!("Label: {}", &loaded_label));
+			ui.label(format!("Label: {}", loaded_label));
 		});
 }
 
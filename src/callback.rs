@@ -0,0 +1,63 @@
+use crate::{
+	checkpoint::EpochMetrics,
+	error::Result,
+	network::{Evaluation, Network},
+};
+
+/// Whether training continues after a dataset iteration.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Control {
+	#[default]
+	Continue,
+	/// Stop training after this dataset iteration, which then counts as the last one.
+	Stop,
+}
+
+/// The result of training on a single batch.
+/// The weights are updated after every image, so every batch is a single image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BatchMetrics {
+	/// The dataset iteration of the batch.
+	pub iteration: usize,
+	/// The position of the batch in the dataset iteration.
+	pub sample: usize,
+	pub label: u8,
+	/// Whether the network predicted the label, before back propagating.
+	pub correct: bool,
+	pub cost: f32,
+}
+
+/// Hooks called by `Network::train_from` during training.
+/// Every hook does nothing by default, so only the needed ones have to be implemented.
+///
+/// The hooks get mutable access to the network, e.g. to change the learning rate
+/// in `on_epoch_start`, and errors they return stop training.
+pub trait Callback {
+	/// Called before every dataset iteration, and when an interrupted one is resumed.
+	/// The learning rate of the schedule has already been set.
+	fn on_epoch_start(&mut self, _network: &mut Network, _iteration: usize) -> Result<()> {
+		Ok(())
+	}
+
+	/// Called after every batch.
+	fn on_batch_end(&mut self, _network: &mut Network, _batch: &BatchMetrics) -> Result<()> {
+		Ok(())
+	}
+
+	/// Whether the network should be evaluated on the test dataset after every dataset
+	/// iteration. It's evaluated if any callback returns true.
+	fn evaluates(&self) -> bool {
+		false
+	}
+
+	/// Called after the network has been evaluated on the test dataset,
+	/// before `on_epoch_end` of the same dataset iteration.
+	fn on_evaluate(&mut self, _network: &mut Network, _evaluation: &Evaluation) -> Result<()> {
+		Ok(())
+	}
+
+	/// Called after every dataset iteration, with the test metrics if the network was evaluated.
+	fn on_epoch_end(&mut self, _network: &mut Network, _metrics: &EpochMetrics) -> Result<Control> {
+		Ok(Control::Continue)
+	}
+}
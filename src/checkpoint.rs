@@ -0,0 +1,167 @@
+use std::path::{Path, PathBuf};
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	config::{Augmentation, ExperimentConfig, Schedule},
+	model::{ModelError, ModelFile},
+	utils::write_atomic,
+};
+
+/// The newest checkpoint format this version of the crate can read and write.
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+/// Accuracy, cost and training statistics of a single dataset iteration.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EpochMetrics {
+	pub iteration: usize,
+	pub accuracy: f32,
+	pub correct: usize,
+	pub avg_cost: f32,
+	/// The penalty of the weights after the iteration, which isn't part of `avg_cost`.
+	#[serde(default)]
+	pub regularization_cost: f32,
+	/// Accuracy on the test dataset after the iteration, if it was evaluated.
+	#[serde(default)]
+	pub validation_accuracy: Option<f32>,
+	/// Average cost on the test dataset after the iteration, if it was evaluated.
+	#[serde(default)]
+	pub validation_cost: Option<f32>,
+	#[serde(default)]
+	pub learning_rate: f32,
+	/// Seconds spent training during the iteration.
+	#[serde(default)]
+	pub seconds: f32,
+	/// The average norm of the gradients of every image, 0 if the iteration didn't back propagate.
+	#[serde(default)]
+	pub gradient_norm: f32,
+}
+
+/// Everything besides the weights that is needed to continue a training run.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrainingState {
+	/// The total amount of dataset iterations of the run.
+	pub iterations: usize,
+	/// The dataset iteration currently running.
+	pub iteration: usize,
+	/// The amount of training images already processed in the current iteration.
+	pub sample: usize,
+	/// The order the training images are processed in during the current iteration.
+	pub order: Vec<usize>,
+	/// Whether the training images are shuffled before every iteration.
+	pub shuffle: bool,
+	/// Images classified correctly so far in the current iteration.
+	pub correct: usize,
+	/// Sum of the costs so far in the current iteration.
+	pub cost_sum: f32,
+	/// Sum of the gradient norms so far in the current iteration.
+	#[serde(default)]
+	pub gradient_norm_sum: f32,
+	/// Seconds spent on the current iteration so far, including before an interruption.
+	#[serde(default)]
+	pub seconds: f32,
+	/// The random number generator used for training.
+	pub rng: ChaCha8Rng,
+	/// The learning rate at the start of training, which the schedule is based on.
+	#[serde(default)]
+	pub base_learning_rate: Option<f32>,
+	#[serde(default)]
+	pub schedule: Schedule,
+	#[serde(default)]
+	pub augmentation: Augmentation,
+	/// Metrics of every completed dataset iteration.
+	pub history: Vec<EpochMetrics>,
+}
+
+/// A snapshot of a training run that can be resumed.
+/// Plain gradient descent has no optimizer state besides the learning rate,
+/// which is part of the model metadata.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+	pub version: u32,
+	pub model: ModelFile,
+	pub state: TrainingState,
+}
+
+/// When and where checkpoints are written during training.
+#[derive(Clone, Debug)]
+pub struct CheckpointOptions {
+	pub path: PathBuf,
+	/// Write a checkpoint after every `every` dataset iterations.
+	/// Checkpoints are always written when training is interrupted.
+	pub every: Option<usize>,
+}
+
+impl TrainingState {
+	pub fn new(iterations: usize, shuffle: bool, seed: u64) -> TrainingState {
+		TrainingState {
+			iterations,
+			iteration: 0,
+			sample: 0,
+			order: Vec::new(),
+			shuffle,
+			correct: 0,
+			cost_sum: 0.,
+			gradient_norm_sum: 0.,
+			seconds: 0.,
+			rng: ChaCha8Rng::seed_from_u64(seed),
+			base_learning_rate: None,
+			schedule: Schedule::default(),
+			augmentation: Augmentation::default(),
+			history: Vec::new(),
+		}
+	}
+
+	/// The training state at the start of the run described by `config`.
+	/// A random seed is used if the config has none.
+	pub fn from_config(config: &ExperimentConfig) -> TrainingState {
+		// The first dataset iteration doesn't back propagate, so one extra is added.
+		let mut state = TrainingState::new(
+			config.iterations + 1,
+			config.shuffle,
+			config.seed.unwrap_or_else(rand::random),
+		);
+		state.base_learning_rate = Some(config.learning_rate());
+		state.schedule = config.schedule;
+		state.augmentation = config.augmentation;
+		state
+	}
+
+	/// Whether every dataset iteration of the run has completed.
+	pub fn is_finished(&self) -> bool {
+		self.iteration >= self.iterations
+	}
+}
+
+impl Checkpoint {
+	pub fn new(model: ModelFile, state: TrainingState) -> Checkpoint {
+		Checkpoint {
+			version: CHECKPOINT_VERSION,
+			model,
+			state,
+		}
+	}
+
+	/// Read a checkpoint, failing if it was written by a newer version.
+	pub fn read(path: impl AsRef<Path>) -> Result<Checkpoint, ModelError> {
+		let contents = std::fs::read_to_string(path)?;
+		let checkpoint: Checkpoint = serde_json::from_str(&contents)?;
+
+		if checkpoint.version > CHECKPOINT_VERSION {
+			return Err(ModelError::UnsupportedVersion {
+				found: checkpoint.version,
+				supported: CHECKPOINT_VERSION,
+			});
+		}
+
+		Ok(checkpoint)
+	}
+
+	/// Write the checkpoint, replacing any previous checkpoint atomically.
+	pub fn write(&self, path: impl AsRef<Path>) -> Result<(), ModelError> {
+		write_atomic(path, serde_json::to_string(self)?.as_bytes())?;
+		Ok(())
+	}
+}
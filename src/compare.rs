@@ -0,0 +1,351 @@
+use std::sync::Arc;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+	config,
+	dataset::Dataset,
+	error::{Error, Result},
+	network::{Evaluation, Network},
+};
+
+/// The amount of resamples used for the bootstrap confidence intervals.
+const BOOTSTRAP_SAMPLES: usize = 1_000;
+/// Seed for the bootstrap, so comparing the same models twice gives the same intervals.
+const BOOTSTRAP_SEED: u64 = 0x006d_6e69_7374;
+/// Below this many discordant pairs McNemar's test uses the exact binomial distribution.
+const EXACT_MCNEMAR_LIMIT: usize = 25;
+
+/// A saved model and how it performed on the test dataset.
+#[derive(Clone, Debug)]
+pub struct ModelEvaluation {
+	pub name: String,
+	pub evaluation: Evaluation,
+	/// 95% bootstrap confidence interval of the accuracy in percent.
+	pub accuracy_interval: (f32, f32),
+}
+
+/// An image the two compared models classified differently.
+#[derive(Clone, Debug)]
+pub struct Disagreement {
+	pub index: usize,
+	pub label: u8,
+	pub baseline: Option<usize>,
+	pub candidate: Option<usize>,
+}
+
+/// Result of McNemar's test on the paired predictions of two models.
+#[derive(Clone, Debug)]
+pub struct McNemar {
+	/// Images only the baseline classified correctly.
+	pub baseline_only: usize,
+	/// Images only the candidate classified correctly.
+	pub candidate_only: usize,
+	/// The chi-squared statistic, or `None` if the exact test was used.
+	pub statistic: Option<f64>,
+	pub p_value: f64,
+}
+
+/// Comparison of a candidate model against the baseline model.
+#[derive(Clone, Debug)]
+pub struct Comparison {
+	pub baseline: String,
+	pub candidate: String,
+	/// Candidate accuracy minus baseline accuracy in percentage points.
+	pub accuracy_delta: f32,
+	/// 95% bootstrap confidence interval of the accuracy delta.
+	pub delta_interval: (f32, f32),
+	pub mcnemar: McNemar,
+	pub disagreements: Vec<Disagreement>,
+}
+
+/// Evaluate every model on the test dataset.
+/// Every model is loaded with the shape stored in its file,
+/// and all of them must have been trained on the same dataset.
+pub fn evaluate_models(models: &[String]) -> Result<Vec<ModelEvaluation>> {
+	let mut evaluations = Vec::with_capacity(models.len());
+	let mut dataset: Option<(config::Dataset, Arc<Dataset>)> = None;
+
+	for model in models {
+		let mut network = Network::from_file(model)?;
+		let kind = network
+			.config
+			.as_ref()
+			.map(|config| config.dataset)
+			.unwrap_or_default();
+
+		match &dataset {
+			Some((first, _)) if *first != kind => {
+				return Err(Error::Dataset(format!(
+					"{} was trained on {}, but {} on {}",
+					models[0],
+					first.name(),
+					model,
+					kind.name()
+				)));
+			}
+			Some((_, shared)) => network.dataset = Some(shared.clone()),
+			None => dataset = Some((kind, network.dataset()?)),
+		}
+		let evaluation = network.evaluate()?;
+		let accuracy_interval = bootstrap(evaluation.len(), |indices| {
+			accuracy_of(&evaluation, indices)
+		});
+
+		evaluations.push(ModelEvaluation {
+			name: model.clone(),
+			evaluation,
+			accuracy_interval,
+		});
+	}
+
+	Ok(evaluations)
+}
+
+/// Compare every model against the first one, which is used as the baseline.
+pub fn compare(evaluations: &[ModelEvaluation]) -> Result<Vec<Comparison>> {
+	let Some((baseline, candidates)) = evaluations.split_first() else {
+		return Ok(Vec::new());
+	};
+
+	candidates
+		.iter()
+		.map(|candidate| compare_pair(baseline, candidate))
+		.collect()
+}
+
+/// Compare a single candidate against the baseline.
+/// Both need to have been evaluated on the same images.
+pub fn compare_pair(baseline: &ModelEvaluation, candidate: &ModelEvaluation) -> Result<Comparison> {
+	let (base, cand) = (&baseline.evaluation, &candidate.evaluation);
+	if base.labels != cand.labels {
+		return Err(Error::Dataset(format!(
+			"{} and {} were not evaluated on the same images",
+			baseline.name, candidate.name
+		)));
+	}
+
+	let mut disagreements = Vec::new();
+	let mut baseline_only = 0;
+	let mut candidate_only = 0;
+
+	for index in 0..base.len() {
+		match (base.is_correct(index), cand.is_correct(index)) {
+			(true, false) => baseline_only += 1,
+			(false, true) => candidate_only += 1,
+			_ => {}
+		}
+
+		if base.predictions[index] != cand.predictions[index] {
+			disagreements.push(Disagreement {
+				index,
+				label: base.labels[index],
+				baseline: base.predictions[index],
+				candidate: cand.predictions[index],
+			});
+		}
+	}
+
+	let delta_interval = bootstrap(base.len(), |indices| {
+		accuracy_of(cand, indices) - accuracy_of(base, indices)
+	});
+
+	Ok(Comparison {
+		baseline: baseline.name.clone(),
+		candidate: candidate.name.clone(),
+		accuracy_delta: cand.accuracy() - base.accuracy(),
+		delta_interval,
+		mcnemar: mcnemar(baseline_only, candidate_only),
+		disagreements,
+	})
+}
+
+/// McNemar's test for the discordant pairs of two classifiers.
+/// Uses the exact binomial test for few discordant pairs, and the
+/// continuity corrected chi-squared approximation otherwise.
+pub fn mcnemar(baseline_only: usize, candidate_only: usize) -> McNemar {
+	let discordant = baseline_only + candidate_only;
+
+	if discordant == 0 {
+		return McNemar {
+			baseline_only,
+			candidate_only,
+			statistic: None,
+			p_value: 1.,
+		};
+	}
+
+	if discordant < EXACT_MCNEMAR_LIMIT {
+		// Two sided binomial test with p = 0.5.
+		let smallest = baseline_only.min(candidate_only);
+		let tail: f64 = (0..=smallest)
+			.map(|k| binomial(discordant, k) * 0.5_f64.powi(discordant as i32))
+			.sum();
+
+		return McNemar {
+			baseline_only,
+			candidate_only,
+			statistic: None,
+			p_value: (2. * tail).min(1.),
+		};
+	}
+
+	let difference = (baseline_only as f64 - candidate_only as f64).abs() - 1.;
+	let statistic = difference.max(0.).powi(2) / discordant as f64;
+
+	McNemar {
+		baseline_only,
+		candidate_only,
+		statistic: Some(statistic),
+		// Survival function of the chi-squared distribution with one degree of freedom.
+		p_value: erfc((statistic / 2.).sqrt()),
+	}
+}
+
+/// Accuracy in percent of the images at `indices`.
+fn accuracy_of(evaluation: &Evaluation, indices: &[usize]) -> f32 {
+	let correct = indices
+		.iter()
+		.filter(|i| evaluation.is_correct(**i))
+		.count();
+	(correct as f32 / indices.len() as f32) * 100.
+}
+
+/// 95% percentile bootstrap interval of `statistic` over `len` samples.
+fn bootstrap(len: usize, statistic: impl Fn(&[usize]) -> f32) -> (f32, f32) {
+	if len == 0 {
+		return (0., 0.);
+	}
+
+	let mut rng = ChaCha8Rng::seed_from_u64(BOOTSTRAP_SEED);
+	let mut indices = vec![0; len];
+	let mut values = Vec::with_capacity(BOOTSTRAP_SAMPLES);
+
+	for _ in 0..BOOTSTRAP_SAMPLES {
+		for index in indices.iter_mut() {
+			*index = rng.gen_range(0..len);
+		}
+		values.push(statistic(&indices));
+	}
+
+	values.sort_by(f32::total_cmp);
+	let lower = values[(BOOTSTRAP_SAMPLES as f32 * 0.025) as usize];
+	let upper = values[(BOOTSTRAP_SAMPLES as f32 * 0.975) as usize - 1];
+
+	(lower, upper)
+}
+
+/// The binomial coefficient "n choose k" as a float.
+fn binomial(n: usize, k: usize) -> f64 {
+	(0..k).fold(1., |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+/// Complementary error function, accurate to about 1.2e-7.
+fn erfc(x: f64) -> f64 {
+	let z = x.abs();
+	let t = 1. / (1. + 0.5 * z);
+	let poly = -z * z - 1.265_512_23
+		+ t * (1.000_023_68
+			+ t * (0.374_091_96
+				+ t * (0.096_784_18
+					+ t * (-0.186_288_06
+						+ t * (0.278_868_07
+							+ t * (-1.135_203_98
+								+ t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+	let result = t * poly.exp();
+
+	if x >= 0. {
+		result
+	} else {
+		2. - result
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn evaluation(name: &str, labels: &[u8], predictions: &[usize]) -> ModelEvaluation {
+		ModelEvaluation {
+			name: name.to_string(),
+			evaluation: Evaluation {
+				labels: labels.to_vec(),
+				predictions: predictions.iter().map(|p| Some(*p)).collect(),
+				costs: vec![0.; labels.len()],
+			},
+			accuracy_interval: (0., 0.),
+		}
+	}
+
+	#[test]
+	fn erfc_known_values() {
+		assert!((erfc(0.) - 1.).abs() < 1e-7);
+		assert!((erfc(1.) - 0.157_299_2).abs() < 1e-6);
+		assert!((erfc(-1.) - 1.842_700_8).abs() < 1e-6);
+		assert!((erfc(0.5) - 0.479_500_1).abs() < 1e-6);
+	}
+
+	#[test]
+	fn exact_mcnemar() {
+		// P(X <= 1) for X ~ Binomial(6, 0.5) is 7/64, doubled for the two sided test.
+		let result = mcnemar(1, 5);
+		assert_eq!(result.statistic, None);
+		assert!((result.p_value - 14. / 64.).abs() < 1e-12);
+		assert_eq!(mcnemar(5, 1).p_value, result.p_value);
+
+		assert_eq!(mcnemar(0, 0).p_value, 1.);
+		assert_eq!(mcnemar(3, 3).p_value, 1.);
+	}
+
+	#[test]
+	fn approximate_mcnemar() {
+		// (|10 - 30| - 1)² / 40 = 9.025, with a p-value of 0.002663 for one degree of freedom.
+		let result = mcnemar(10, 30);
+		assert!((result.statistic.unwrap() - 9.025).abs() < 1e-12);
+		assert!((result.p_value - 0.002_663_1).abs() < 1e-6);
+	}
+
+	#[test]
+	fn bootstrap_interval() {
+		assert_eq!(bootstrap(0, |_| 1.), (0., 0.));
+		assert_eq!(bootstrap(10, |_| 42.), (42., 42.));
+
+		// Every other image is classified correctly.
+		let labels: Vec<u8> = (0..1_000).map(|i| (i % 10) as u8).collect();
+		let predictions: Vec<usize> = (0..1_000)
+			.map(|i| if i % 2 == 0 { i % 10 } else { 0 })
+			.collect();
+		let evaluation = evaluation("a", &labels, &predictions).evaluation;
+		let accuracy = evaluation.accuracy();
+
+		let (lower, upper) = bootstrap(evaluation.len(), |indices| {
+			accuracy_of(&evaluation, indices)
+		});
+		assert!(lower < accuracy && accuracy < upper);
+		// The standard error of the accuracy is about 1.6%.
+		assert!(upper - lower > 4. && upper - lower < 8.);
+
+		// The same seed gives the same interval every time.
+		let again = bootstrap(evaluation.len(), |indices| {
+			accuracy_of(&evaluation, indices)
+		});
+		assert_eq!((lower, upper), again);
+	}
+
+	#[test]
+	fn compares_on_the_same_images_only() {
+		let baseline = evaluation("a", &[1, 2, 3], &[1, 2, 0]);
+		let candidate = evaluation("b", &[1, 2, 3], &[1, 0, 3]);
+		let comparison = compare_pair(&baseline, &candidate).unwrap();
+		assert_eq!(comparison.mcnemar.baseline_only, 1);
+		assert_eq!(comparison.mcnemar.candidate_only, 1);
+		assert_eq!(comparison.disagreements.len(), 2);
+
+		let other = evaluation("c", &[1, 2, 4], &[1, 2, 4]);
+		assert!(matches!(
+			compare(&[baseline, candidate, other]),
+			Err(Error::Dataset(_))
+		));
+	}
+}
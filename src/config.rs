@@ -0,0 +1,529 @@
+use std::{fs, path::Path};
+
+use clap::ValueEnum;
+use ndarray::ArrayView2;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::utils::{activation_functions::Activation, loss_functions::Loss};
+
+/// Errors that can happen while reading an experiment config file.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+	#[error("could not read config file: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("could not parse TOML config: {0}")]
+	Toml(#[from] toml::de::Error),
+	#[error("could not parse YAML config: {0}")]
+	Yaml(#[from] serde_yaml::Error),
+	#[error("could not write TOML config: {0}")]
+	TomlSerialize(#[from] toml::ser::Error),
+	#[error("could not parse JSON config: {0}")]
+	Json(#[from] serde_json::Error),
+	#[error("unknown config file extension {0:?}, expected .toml, .yaml, .yml or .json")]
+	UnknownFormat(String),
+	#[error("invalid config: {0}")]
+	Invalid(String),
+}
+
+/// The largest seed a config can have, TOML integers are signed 64 bit.
+pub const MAX_SEED: u64 = i64::MAX as u64;
+
+/// Everything needed to reproduce a training run.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExperimentConfig {
+	pub dataset: Dataset,
+	/// The amount of neurons in every hidden layer.
+	pub hidden: Vec<usize>,
+	/// The activation function of the hidden layers.
+	pub activation: Activation,
+	/// The dropout rate of every hidden layer while training, or a single rate for all of them.
+	/// Empty disables dropout.
+	pub dropout: Vec<f32>,
+	pub loss: Loss,
+	pub optimizer: Optimizer,
+	pub schedule: Schedule,
+	pub regularization: Regularization,
+	pub augmentation: Augmentation,
+	/// The amount of dataset iterations to train for.
+	pub iterations: usize,
+	/// Shuffle the training images before every dataset iteration.
+	pub shuffle: bool,
+	/// Seed for the initial weights and the random number generator used during training.
+	pub seed: Option<u64>,
+	/// Where the trained network is saved.
+	pub output: Option<String>,
+}
+
+/// The datasets a network can be trained on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Dataset {
+	/// Handwritten digits, loaded from the `data` folder.
+	#[default]
+	Mnist,
+	/// Points labeled by the XOR of the signs of their coordinates.
+	Xor,
+	/// Points on two interleaved spirals.
+	Spirals,
+	/// Points around four centers.
+	Blobs,
+	/// Procedurally rendered 28×28 images of digits.
+	Digits,
+}
+
+/// How the weights are updated.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Optimizer {
+	/// Plain stochastic gradient descent.
+	Sgd { learning_rate: f32 },
+}
+
+/// How the learning rate changes between dataset iterations.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Schedule {
+	/// Keep the learning rate the same.
+	#[default]
+	Constant,
+	/// Multiply the learning rate by `factor` after every `every` dataset iterations.
+	Step { every: usize, factor: f32 },
+	/// Multiply the learning rate by `factor` after every dataset iteration.
+	Exponential { factor: f32 },
+}
+
+/// Penalties and constraints on the weights, the biases are never regularized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Regularization {
+	/// Strength of the L1 penalty `l1 * Σ|w|`, which pushes weights to exactly 0.
+	pub l1: f32,
+	/// Strength of the L2 penalty `l2 / 2 * Σw²`, which keeps weights small.
+	pub l2: f32,
+	/// Apply the L2 penalty as decoupled weight decay, which only differs from L2 with adaptive optimizers.
+	pub decoupled: bool,
+	/// The largest L2 norm the weights of a neuron may have,
+	/// they are scaled down to it after every update.
+	pub max_norm: Option<f32>,
+}
+
+/// Random changes made to the training images before they are fed forward.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Augmentation {
+	/// Move every image up to this many pixels in both directions.
+	pub shift: usize,
+}
+
+impl Default for ExperimentConfig {
+	fn default() -> ExperimentConfig {
+		ExperimentConfig {
+			dataset: Dataset::default(),
+			hidden: vec![16, 16],
+			activation: Activation::default(),
+			dropout: Vec::new(),
+			loss: Loss::default(),
+			optimizer: Optimizer::default(),
+			schedule: Schedule::default(),
+			regularization: Regularization::default(),
+			augmentation: Augmentation::default(),
+			iterations: 100,
+			shuffle: false,
+			seed: None,
+			output: None,
+		}
+	}
+}
+
+impl ExperimentConfig {
+	/// Read a config file, the format is picked from the extension.
+	/// Missing fields use their default values.
+	pub fn read(path: impl AsRef<Path>) -> Result<ExperimentConfig, ConfigError> {
+		let path = path.as_ref();
+		let contents = fs::read_to_string(path)?;
+		let extension = path
+			.extension()
+			.map(|extension| extension.to_string_lossy().to_lowercase())
+			.unwrap_or_default();
+
+		let config: ExperimentConfig = match extension.as_str() {
+			"toml" => toml::from_str(&contents)?,
+			"yaml" | "yml" => serde_yaml::from_str(&contents)?,
+			"json" => serde_json::from_str(&contents)?,
+			_ => return Err(ConfigError::UnknownFormat(extension)),
+		};
+
+		config.validate()?;
+		Ok(config)
+	}
+
+	/// Check that the config describes a network that can be trained.
+	pub fn validate(&self) -> Result<(), ConfigError> {
+		if self.hidden.contains(&0) {
+			return Err(ConfigError::Invalid(
+				"hidden layers need at least one neuron".to_string(),
+			));
+		}
+
+		if let Some(rate) = self.dropout.iter().find(|rate| !(0. ..1.).contains(*rate)) {
+			return Err(ConfigError::Invalid(format!(
+				"dropout rate {} is not in [0, 1)",
+				rate
+			)));
+		}
+
+		let Regularization {
+			l1, l2, max_norm, ..
+		} = self.regularization;
+		if !(l1 >= 0. && l1.is_finite() && l2 >= 0. && l2.is_finite()) {
+			return Err(ConfigError::Invalid(
+				"l1 and l2 need to be at least 0".to_string(),
+			));
+		}
+		if max_norm.is_some_and(|max_norm| !(max_norm > 0. && max_norm.is_finite())) {
+			return Err(ConfigError::Invalid(
+				"max_norm needs to be greater than 0".to_string(),
+			));
+		}
+
+		let learning_rate = self.learning_rate();
+		if !(learning_rate > 0. && learning_rate.is_finite()) {
+			return Err(ConfigError::Invalid(
+				"learning_rate needs to be greater than 0".to_string(),
+			));
+		}
+
+		if self.seed.is_some_and(|seed| seed > MAX_SEED) {
+			return Err(ConfigError::Invalid(format!(
+				"seed needs to be at most {}",
+				MAX_SEED
+			)));
+		}
+
+		if let Schedule::Step { every: 0, .. } = self.schedule {
+			return Err(ConfigError::Invalid(
+				"step schedule needs `every` to be at least 1".to_string(),
+			));
+		}
+		if let Schedule::Step { factor, .. } | Schedule::Exponential { factor } = self.schedule {
+			if !(factor >= 0. && factor.is_finite()) {
+				return Err(ConfigError::Invalid(
+					"schedule factor needs to be at least 0".to_string(),
+				));
+			}
+		}
+
+		Ok(())
+	}
+
+	pub fn learning_rate(&self) -> f32 {
+		match self.optimizer {
+			Optimizer::Sgd { learning_rate } => learning_rate,
+		}
+	}
+
+	/// The dropout rate of each of `layers` hidden layers, or empty if dropout is disabled.
+	pub fn dropout_rates(&self, layers: usize) -> Result<Vec<f32>, ConfigError> {
+		match self.dropout.as_slice() {
+			[] => Ok(Vec::new()),
+			[rate] => Ok(vec![*rate; layers]),
+			rates if rates.len() == layers => Ok(rates.to_vec()),
+			rates => Err(ConfigError::Invalid(format!(
+				"got {} dropout rates for {} hidden layers",
+				rates.len(),
+				layers
+			))),
+		}
+	}
+
+	pub fn to_toml(&self) -> Result<String, ConfigError> {
+		Ok(toml::to_string(self)?)
+	}
+}
+
+impl Default for Optimizer {
+	fn default() -> Optimizer {
+		Optimizer::Sgd { learning_rate: 0.1 }
+	}
+}
+
+impl Dataset {
+	pub fn name(self) -> &'static str {
+		match self {
+			Dataset::Mnist => "mnist",
+			Dataset::Xor => "xor",
+			Dataset::Spirals => "spirals",
+			Dataset::Blobs => "blobs",
+			Dataset::Digits => "digits",
+		}
+	}
+
+	/// The amount of inputs of every sample.
+	pub fn input_size(self) -> usize {
+		match self {
+			Dataset::Mnist | Dataset::Digits => 784,
+			Dataset::Xor | Dataset::Spirals | Dataset::Blobs => 2,
+		}
+	}
+
+	/// The amount of labels, and so output neurons.
+	pub fn classes(self) -> usize {
+		match self {
+			Dataset::Mnist | Dataset::Digits => 10,
+			Dataset::Xor | Dataset::Spirals => 2,
+			Dataset::Blobs => 4,
+		}
+	}
+}
+
+impl Schedule {
+	/// The learning rate to use during dataset iteration `iteration`.
+	pub fn learning_rate(self, base: f32, iteration: usize) -> f32 {
+		match self {
+			Schedule::Constant => base,
+			Schedule::Step { every, factor } => {
+				base * factor.powi((iteration / every.max(1)) as i32)
+			}
+			Schedule::Exponential { factor } => base * factor.powi(iteration as i32),
+		}
+	}
+}
+
+impl Regularization {
+	/// Whether the weights are regularized at all.
+	pub fn is_enabled(&self) -> bool {
+		self.l1 > 0. || self.l2 > 0. || self.max_norm.is_some()
+	}
+
+	/// The penalty of the weights of a neuron, that is added to the cost.
+	pub fn penalty(&self, weights: &[f32]) -> f32 {
+		let l1: f32 = weights.iter().map(|weight| weight.abs()).sum();
+		let l2: f32 = weights.iter().map(|weight| weight * weight).sum();
+		self.l1 * l1 + self.l2 / 2. * l2
+	}
+
+	/// Update the weights of a neuron with their gradients of the cost, including the penalties,
+	/// and scale them down to the max norm.
+	pub fn step(&self, weights: &mut [f32], gradients: &[f32], learning_rate: f32) {
+		for (weight, gradient) in weights.iter_mut().zip(gradients) {
+			let mut gradient = gradient + self.l1 * sign(*weight);
+			if self.decoupled {
+				*weight -= learning_rate * self.l2 * *weight;
+			} else {
+				gradient += self.l2 * *weight;
+			}
+			*weight -= learning_rate * gradient;
+		}
+
+		if let Some(max_norm) = self.max_norm {
+			let norm = weights
+				.iter()
+				.map(|weight| weight * weight)
+				.sum::<f32>()
+				.sqrt();
+			if norm > max_norm {
+				let scale = max_norm / norm;
+				weights.iter_mut().for_each(|weight| *weight *= scale);
+			}
+		}
+	}
+}
+
+/// The sign of `value`, 0 for 0 so L1 leaves zero weights alone.
+fn sign(value: f32) -> f32 {
+	if value > 0. {
+		1.
+	} else if value < 0. {
+		-1.
+	} else {
+		0.
+	}
+}
+
+impl Augmentation {
+	/// Apply the augmentation to an image, returning its pixels as a 1D array.
+	pub fn apply(&self, image: ArrayView2<f32>, rng: &mut impl Rng) -> Vec<f32> {
+		if self.shift == 0 {
+			return image.iter().copied().collect();
+		}
+
+		let shift = self.shift as isize;
+		let dx = rng.gen_range(-shift..=shift);
+		let dy = rng.gen_range(-shift..=shift);
+		let (height, width) = image.dim();
+		let mut pixels = vec![0.; height * width];
+
+		for ((y, x), pixel) in image.indexed_iter() {
+			let (new_y, new_x) = (y as isize + dy, x as isize + dx);
+			if (0..height as isize).contains(&new_y) && (0..width as isize).contains(&new_x) {
+				pixels[new_y as usize * width + new_x as usize] = *pixel;
+			}
+		}
+
+		pixels
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+
+	use super::*;
+
+	/// Write `contents` to a temporary config file with the given extension and read it.
+	fn read(extension: &str, contents: &str) -> Result<ExperimentConfig, ConfigError> {
+		let path = std::env::temp_dir().join(format!(
+			"mnist-ai-config-{}-{}.{}",
+			std::process::id(),
+			extension,
+			extension
+		));
+		fs::write(&path, contents).unwrap();
+		let config = ExperimentConfig::read(&path);
+		let _ = fs::remove_file(&path);
+		config
+	}
+
+	fn expected() -> ExperimentConfig {
+		ExperimentConfig {
+			dataset: Dataset::Blobs,
+			hidden: vec![8, 4],
+			optimizer: Optimizer::Sgd {
+				learning_rate: 0.05,
+			},
+			schedule: Schedule::Exponential { factor: 0.9 },
+			regularization: Regularization {
+				l2: 0.01,
+				..Regularization::default()
+			},
+			iterations: 3,
+			seed: Some(7),
+			..ExperimentConfig::default()
+		}
+	}
+
+	#[test]
+	fn reads_every_format() {
+		let toml = r#"
+			dataset = "blobs"
+			hidden = [8, 4]
+			iterations = 3
+			seed = 7
+
+			[optimizer]
+			kind = "sgd"
+			learning_rate = 0.05
+
+			[schedule]
+			kind = "exponential"
+			factor = 0.9
+
+			[regularization]
+			l2 = 0.01
+		"#;
+		assert_eq!(read("toml", toml).unwrap(), expected());
+
+		let yaml = "
+dataset: blobs
+hidden: [8, 4]
+iterations: 3
+seed: 7
+optimizer:
+  kind: sgd
+  learning_rate: 0.05
+schedule:
+  kind: exponential
+  factor: 0.9
+regularization:
+  l2: 0.01
+";
+		assert_eq!(read("yaml", yaml).unwrap(), expected());
+		assert_eq!(read("yml", yaml).unwrap(), expected());
+
+		let json = serde_json::to_string(&expected()).unwrap();
+		assert_eq!(read("json", &json).unwrap(), expected());
+
+		// Saved configs can be read back.
+		assert_eq!(
+			read("toml", &expected().to_toml().unwrap()).unwrap(),
+			expected()
+		);
+	}
+
+	#[test]
+	fn missing_fields_use_the_defaults() {
+		assert_eq!(read("toml", "").unwrap(), ExperimentConfig::default());
+		assert_eq!(read("json", "{}").unwrap(), ExperimentConfig::default());
+	}
+
+	#[test]
+	fn rejects_unknown_fields_and_formats() {
+		assert!(matches!(
+			read("toml", "hiden = [8]"),
+			Err(ConfigError::Toml(_))
+		));
+		assert!(matches!(
+			read("yaml", "regularization:\n  l3: 0.1\n"),
+			Err(ConfigError::Yaml(_))
+		));
+		assert!(matches!(
+			read(
+				"json",
+				r#"{"optimizer": {"kind": "sgd", "learning_rate": 0.1, "momentum": 0.9}}"#
+			),
+			Err(ConfigError::Json(_))
+		));
+		assert!(matches!(
+			read("ini", ""),
+			Err(ConfigError::UnknownFormat(extension)) if extension == "ini"
+		));
+		assert!(matches!(
+			ExperimentConfig::read(PathBuf::from("missing.toml")),
+			Err(ConfigError::Io(_))
+		));
+	}
+
+	#[test]
+	fn validates_the_learning_rate_and_schedule() {
+		for learning_rate in [0., -0.1, f32::NAN, f32::INFINITY] {
+			let config = ExperimentConfig {
+				optimizer: Optimizer::Sgd { learning_rate },
+				..ExperimentConfig::default()
+			};
+			assert!(config.validate().is_err(), "{}", learning_rate);
+		}
+
+		for schedule in [
+			Schedule::Exponential { factor: -0.5 },
+			Schedule::Exponential { factor: f32::NAN },
+			Schedule::Step {
+				every: 2,
+				factor: -1.,
+			},
+			Schedule::Step {
+				every: 2,
+				factor: f32::NAN,
+			},
+			Schedule::Step {
+				every: 0,
+				factor: 0.5,
+			},
+		] {
+			let config = ExperimentConfig {
+				schedule,
+				..ExperimentConfig::default()
+			};
+			assert!(config.validate().is_err(), "{:?}", schedule);
+		}
+
+		// Invalid values in a file are rejected when it's read.
+		assert!(matches!(
+			read("toml", "[optimizer]\nkind = \"sgd\"\nlearning_rate = -0.1"),
+			Err(ConfigError::Invalid(_))
+		));
+		assert!(expected().validate().is_ok());
+	}
+}
@@ -0,0 +1,365 @@
+use std::{f32::consts::PI, fs::File, io::Read, path::Path};
+
+use mnist::{Mnist, MnistBuilder};
+use ndarray::{Array2, Array3};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+	config,
+	error::{Error, Result},
+	predict::IMAGE_SIZE,
+};
+
+/// The folder the MNIST files are loaded from.
+pub const MNIST_DIR: &str = "data";
+/// The MNIST files, as they are named when downloaded and unpacked.
+pub const MNIST_FILES: [&str; 4] = [
+	"train-images-idx3-ubyte",
+	"train-labels-idx1-ubyte",
+	"t10k-images-idx3-ubyte",
+	"t10k-labels-idx1-ubyte",
+];
+/// The header every MNIST file starts with: the magic number, the amount of items and,
+/// for images, the amount of rows and columns.
+const MNIST_HEADERS: [&[u32]; 4] = [
+	&[IDX_IMAGES, 60_000, 28, 28],
+	&[IDX_LABELS, 60_000],
+	&[IDX_IMAGES, 10_000, 28, 28],
+	&[IDX_LABELS, 10_000],
+];
+/// The magic number of IDX files of images.
+const IDX_IMAGES: u32 = 2051;
+/// The magic number of IDX files of labels.
+const IDX_LABELS: u32 = 2049;
+/// The amount of training samples of the synthetic datasets.
+pub const SYNTHETIC_TRAINING: usize = 2000;
+/// The amount of test samples of the synthetic datasets.
+pub const SYNTHETIC_TEST: usize = 500;
+/// The seed the synthetic datasets are generated with.
+const SYNTHETIC_SEED: u64 = 0;
+
+/// Images and labels to train and test a network on.
+/// Pixels are between 0 and 1 for images, the synthetic 2D datasets store every point
+/// as an image of 1×2 pixels. Every label is a single digit.
+#[derive(Clone, Debug)]
+pub struct Dataset {
+	pub training_images: Array3<f32>,
+	pub training_labels: Array2<u8>,
+	pub test_images: Array3<f32>,
+	pub test_labels: Array2<u8>,
+}
+
+impl Dataset {
+	/// Load the MNIST dataset from the `data` folder.
+	pub fn mnist() -> Result<Dataset> {
+		for (file, header) in MNIST_FILES.iter().zip(MNIST_HEADERS) {
+			let path = Path::new(MNIST_DIR).join(file);
+			if !path.is_file() {
+				return Err(Error::Dataset(format!(
+					"missing MNIST file {}",
+					path.display()
+				)));
+			}
+			// The mnist crate panics on files it can't parse, so they are checked first.
+			check_idx(&path, header)?;
+		}
+
+		let Mnist {
+			trn_img,
+			trn_lbl,
+			tst_img,
+			tst_lbl,
+			..
+		} = MnistBuilder::new()
+			.base_path(MNIST_DIR)
+			.label_format_digit()
+			.training_set_length(50_000)
+			.test_set_length(10_000)
+			.finalize();
+
+		let training_images =
+			Array3::from_shape_vec((50_000, 28, 28), trn_img)?.map(|x| *x as f32 / 256.0);
+		let training_labels = Array2::from_shape_vec((50_000, 1), trn_lbl)?;
+
+		let test_images =
+			Array3::from_shape_vec((10_000, 28, 28), tst_img)?.map(|x| *x as f32 / 256.);
+		let test_labels = Array2::from_shape_vec((10_000, 1), tst_lbl)?;
+
+		Ok(Dataset {
+			training_images,
+			training_labels,
+			test_images,
+			test_labels,
+		})
+	}
+
+	/// Load the dataset described by a config.
+	/// Synthetic datasets are generated with a fixed seed, so they are the same on every run.
+	pub fn load(kind: config::Dataset) -> Result<Dataset> {
+		let mut rng = ChaCha8Rng::seed_from_u64(SYNTHETIC_SEED);
+		let (training, test) = (SYNTHETIC_TRAINING, SYNTHETIC_TEST);
+
+		Ok(match kind {
+			config::Dataset::Mnist => Dataset::mnist()?,
+			config::Dataset::Xor => Dataset::xor(training, test, &mut rng),
+			config::Dataset::Spirals => Dataset::spirals(training, test, &mut rng),
+			config::Dataset::Blobs => Dataset::blobs(training, test, &mut rng),
+			config::Dataset::Digits => Dataset::digits(training, test, &mut rng),
+		})
+	}
+
+	/// Points between -1 and 1, labeled 1 when exactly one of the coordinates is positive.
+	pub fn xor(training: usize, test: usize, rng: &mut impl Rng) -> Dataset {
+		Dataset::generate(training, test, (1, 2), 2, rng, |_, rng| {
+			let (x, y) = (rng.gen_range(-1. ..1.), rng.gen_range(-1. ..1.));
+			let label = (x > 0.) != (y > 0.);
+			(vec![x, y], label as u8)
+		})
+	}
+
+	/// Points on two interleaved spirals, one for each label.
+	pub fn spirals(training: usize, test: usize, rng: &mut impl Rng) -> Dataset {
+		Dataset::generate(training, test, (1, 2), 2, rng, |label, rng| {
+			let distance: f32 = rng.gen();
+			// One and a half turns, with the second spiral rotated by half a turn.
+			let angle = distance * 3. * PI + label as f32 * PI;
+			let x = distance * angle.cos() + gaussian(rng) * 0.02;
+			let y = distance * angle.sin() + gaussian(rng) * 0.02;
+			(vec![x, y], label)
+		})
+	}
+
+	/// Points around four centers, one for each label.
+	pub fn blobs(training: usize, test: usize, rng: &mut impl Rng) -> Dataset {
+		const CENTERS: [(f32, f32); 4] = [(-0.5, -0.5), (0.5, -0.5), (-0.5, 0.5), (0.5, 0.5)];
+
+		Dataset::generate(training, test, (1, 2), CENTERS.len(), rng, |label, rng| {
+			let (x, y) = CENTERS[label as usize];
+			let point = vec![x + gaussian(rng) * 0.15, y + gaussian(rng) * 0.15];
+			(point, label)
+		})
+	}
+
+	/// 28×28 images of digits drawn with straight strokes,
+	/// randomly scaled, slanted, moved and thickened.
+	pub fn digits(training: usize, test: usize, rng: &mut impl Rng) -> Dataset {
+		let size = (IMAGE_SIZE as usize, IMAGE_SIZE as usize);
+		Dataset::generate(training, test, size, GLYPHS.len(), rng, |label, rng| {
+			(render_digit(label, rng), label)
+		})
+	}
+
+	/// The amount of inputs every image has.
+	pub fn input_size(&self) -> usize {
+		let (_, height, width) = self.training_images.dim();
+		height * width
+	}
+
+	/// Generate a dataset from `sample`, which is given the label to generate an image for.
+	/// The labels take turns, so every label is equally common.
+	fn generate<R: Rng>(
+		training: usize,
+		test: usize,
+		(height, width): (usize, usize),
+		classes: usize,
+		rng: &mut R,
+		mut sample: impl FnMut(u8, &mut R) -> (Vec<f32>, u8),
+	) -> Dataset {
+		let mut split = |samples: usize| {
+			let mut images = Vec::with_capacity(samples * height * width);
+			let mut labels = Vec::with_capacity(samples);
+			for index in 0..samples {
+				let (image, label) = sample((index % classes) as u8, rng);
+				images.extend(image);
+				labels.push(label);
+			}
+
+			(
+				Array3::from_shape_vec((samples, height, width), images)
+					.expect("Every sample has the size of an image"),
+				Array2::from_shape_vec((samples, 1), labels).expect("Every sample has a label"),
+			)
+		};
+
+		let (training_images, training_labels) = split(training);
+		let (test_images, test_labels) = split(test);
+		Dataset {
+			training_images,
+			training_labels,
+			test_images,
+			test_labels,
+		}
+	}
+}
+
+/// Check that an IDX file starts with `header` and holds exactly the items it describes.
+/// The second number of the header is the amount of items, the numbers after it their size.
+fn check_idx(path: &Path, header: &[u32]) -> Result<()> {
+	let invalid = |reason: String| {
+		Error::Dataset(format!("invalid MNIST file {}: {}", path.display(), reason))
+	};
+
+	let mut file = File::open(path)?;
+	let mut bytes = vec![0; header.len() * 4];
+	file.read_exact(&mut bytes)
+		.map_err(|_| invalid("the header is cut off".to_string()))?;
+	let found: Vec<u32> = bytes
+		.chunks_exact(4)
+		.map(|chunk| u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+		.collect();
+	if found != header {
+		return Err(invalid(format!(
+			"expected the header {:?}, found {:?}",
+			header, found
+		)));
+	}
+
+	let item_size: u64 = header[2..].iter().map(|size| *size as u64).product();
+	let expected = bytes.len() as u64 + header[1] as u64 * item_size;
+	let len = file.metadata()?.len();
+	if len != expected {
+		return Err(invalid(format!(
+			"expected {} bytes, found {}",
+			expected, len
+		)));
+	}
+
+	Ok(())
+}
+
+/// The strokes of every digit, as lines through points in a box from (0, 0) at the top left
+/// to (1, 1) at the bottom right.
+const GLYPHS: [&[&[(f32, f32)]]; 10] = [
+	&[&[(0., 0.), (1., 0.), (1., 1.), (0., 1.), (0., 0.)]],
+	&[&[(0.2, 0.25), (0.6, 0.), (0.6, 1.)]],
+	&[&[(0., 0.), (1., 0.), (1., 0.5), (0., 0.5), (0., 1.), (1., 1.)]],
+	&[
+		&[(0., 0.), (1., 0.), (1., 1.), (0., 1.)],
+		&[(0.2, 0.5), (1., 0.5)],
+	],
+	&[&[(0., 0.), (0., 0.6), (1., 0.6)], &[(0.8, 0.2), (0.8, 1.)]],
+	&[&[(1., 0.), (0., 0.), (0., 0.5), (1., 0.5), (1., 1.), (0., 1.)]],
+	&[&[(1., 0.), (0., 0.), (0., 1.), (1., 1.), (1., 0.5), (0., 0.5)]],
+	&[&[(0., 0.), (1., 0.), (0.4, 1.)]],
+	&[
+		&[(0., 0.), (1., 0.), (1., 1.), (0., 1.), (0., 0.)],
+		&[(0., 0.5), (1., 0.5)],
+	],
+	&[&[(1., 0.5), (0., 0.5), (0., 0.), (1., 0.), (1., 1.), (0., 1.)]],
+];
+
+/// Draw a digit on a dark background, with pixels between 0 and 1.
+fn render_digit(label: u8, rng: &mut impl Rng) -> Vec<f32> {
+	let size = IMAGE_SIZE as usize;
+	let height = rng.gen_range(14. ..20.);
+	let width = height * rng.gen_range(0.5..0.7);
+	let slant: f32 = rng.gen_range(-0.25..0.25);
+	let thickness = rng.gen_range(1.5..3.);
+	let center_x = IMAGE_SIZE as f32 / 2. + rng.gen_range(-2. ..2.);
+	let center_y = IMAGE_SIZE as f32 / 2. + rng.gen_range(-2. ..2.);
+
+	// Move every point of the glyph into the image, a little differently every time.
+	let strokes: Vec<Vec<(f32, f32)>> = GLYPHS[label as usize]
+		.iter()
+		.map(|stroke| {
+			stroke
+				.iter()
+				.map(|&(x, y)| {
+					let (x, y) = (
+						x + rng.gen_range(-0.05..0.05),
+						y + rng.gen_range(-0.05..0.05),
+					);
+					(
+						center_x + (x - 0.5) * width - slant * (y - 0.5) * height,
+						center_y + (y - 0.5) * height,
+					)
+				})
+				.collect()
+		})
+		.collect();
+
+	let mut pixels = Vec::with_capacity(size * size);
+	for y in 0..size {
+		for x in 0..size {
+			let point = (x as f32 + 0.5, y as f32 + 0.5);
+			let distance = strokes
+				.iter()
+				.flat_map(|stroke| stroke.windows(2))
+				.map(|line| segment_distance(point, line[0], line[1]))
+				.fold(f32::INFINITY, f32::min);
+
+			// Fade out over a pixel at the edge of the stroke.
+			pixels.push((thickness / 2. + 0.5 - distance).clamp(0., 1.));
+		}
+	}
+
+	pixels
+}
+
+/// The distance from `point` to the line segment from `start` to `end`.
+fn segment_distance(point: (f32, f32), start: (f32, f32), end: (f32, f32)) -> f32 {
+	let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+	let length_sq = dx * dx + dy * dy;
+	let t = if length_sq > 0. {
+		(((point.0 - start.0) * dx + (point.1 - start.1) * dy) / length_sq).clamp(0., 1.)
+	} else {
+		0.
+	};
+
+	let (closest_x, closest_y) = (start.0 + t * dx, start.1 + t * dy);
+	((point.0 - closest_x).powi(2) + (point.1 - closest_y).powi(2)).sqrt()
+}
+
+/// A sample of the standard normal distribution.
+fn gaussian(rng: &mut impl Rng) -> f32 {
+	// Box-Muller transform, the first uniform sample must not be 0.
+	let u: f32 = 1. - rng.gen::<f32>();
+	let v: f32 = rng.gen();
+	(-2. * u.ln()).sqrt() * (2. * PI * v).cos()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn idx_file(name: &str, header: &[u32], items: usize) -> std::path::PathBuf {
+		let path = std::env::temp_dir().join(format!("mnist-ai-{}-{}", name, std::process::id()));
+		let mut bytes: Vec<u8> = header
+			.iter()
+			.flat_map(|number| number.to_be_bytes())
+			.collect();
+		bytes.resize(bytes.len() + items, 0);
+		std::fs::write(&path, bytes).unwrap();
+		path
+	}
+
+	#[test]
+	fn checks_idx_files() {
+		let header = [IDX_IMAGES, 3, 2, 2];
+		let check = |name, written: &[u32], items| {
+			let path = idx_file(name, written, items);
+			let result = check_idx(&path, &header);
+			std::fs::remove_file(&path).unwrap();
+			result.map_err(|err| err.to_string())
+		};
+
+		assert!(check("valid", &header, 12).is_ok());
+		assert!(check("magic", &[IDX_LABELS, 3, 2, 2], 12)
+			.unwrap_err()
+			.contains("expected the header"));
+		assert!(check("count", &[IDX_IMAGES, 4, 2, 2], 16)
+			.unwrap_err()
+			.contains("expected the header"));
+		assert!(check("truncated", &header, 11)
+			.unwrap_err()
+			.contains("expected 28 bytes, found 27"));
+		assert!(check("header", &[IDX_IMAGES], 0)
+			.unwrap_err()
+			.contains("cut off"));
+
+		let labels = idx_file("labels", &[IDX_LABELS, 5], 5);
+		assert!(check_idx(&labels, &[IDX_LABELS, 5]).is_ok());
+		std::fs::remove_file(&labels).unwrap();
+	}
+}
@@ -0,0 +1,34 @@
+use thiserror::Error;
+
+use crate::{config::ConfigError, model::ModelError};
+
+/// Everything that can go wrong in the library.
+#[derive(Debug, Error)]
+pub enum Error {
+	#[error("could not load dataset: {0}")]
+	Dataset(String),
+	#[error("invalid shape: {0}")]
+	Shape(String),
+	#[error("could not read or write file: {0}")]
+	Io(#[from] std::io::Error),
+	#[error(transparent)]
+	Model(#[from] ModelError),
+	#[error(transparent)]
+	Config(#[from] ConfigError),
+	#[error("could not serialize: {0}")]
+	Serialization(#[from] serde_json::Error),
+	#[error("image error: {0}")]
+	Image(#[from] image::ImageError),
+	#[error("invalid metrics file: {0}")]
+	Metrics(String),
+	#[error("could not set up logging: {0}")]
+	Logging(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl From<ndarray::ShapeError> for Error {
+	fn from(err: ndarray::ShapeError) -> Error {
+		Error::Shape(err.to_string())
+	}
+}
@@ -0,0 +1,353 @@
+use crate::{network::Network, neuron::Neuron};
+
+/// The gradient of the cost with respect to every weight and bias,
+/// indexed by layer, neuron and weight. The output layer is the last layer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradients {
+	pub weights: Vec<Vec<Vec<f32>>>,
+	pub biases: Vec<Vec<f32>>,
+}
+
+/// How far the analytic gradients of a layer are from the numerical ones.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerCheck {
+	/// The index of the layer, the output layer is the last one.
+	pub layer: usize,
+	/// The amount of weights and biases compared.
+	pub parameters: usize,
+	/// The norm of the difference between the analytic and numerical gradients,
+	/// relative to the norms of the gradients.
+	pub error: f32,
+}
+
+impl Gradients {
+	/// Zero gradients for layers of the given neurons.
+	pub fn zeros(layers: &[&[Neuron]]) -> Gradients {
+		Gradients {
+			weights: layers
+				.iter()
+				.map(|neurons| {
+					neurons
+						.iter()
+						.map(|neuron| vec![0.; neuron.weights.len()])
+						.collect()
+				})
+				.collect(),
+			biases: layers
+				.iter()
+				.map(|neurons| vec![0.; neurons.len()])
+				.collect(),
+		}
+	}
+
+	/// Add the gradients of another sample, e.g. to sum the gradients of a mini-batch.
+	pub fn add(&mut self, other: &Gradients) {
+		let weights = self.weights.iter_mut().flatten().flatten();
+		for (gradient, other) in weights.zip(other.weights.iter().flatten().flatten()) {
+			*gradient += other;
+		}
+		for (gradient, other) in self
+			.biases
+			.iter_mut()
+			.flatten()
+			.zip(other.biases.iter().flatten())
+		{
+			*gradient += other;
+		}
+	}
+
+	/// The euclidean norm of all gradients together.
+	pub fn norm(&self) -> f32 {
+		let weights = self.weights.iter().flatten().flatten();
+		weights
+			.chain(self.biases.iter().flatten())
+			.map(|gradient| gradient * gradient)
+			.sum::<f32>()
+			.sqrt()
+	}
+
+	/// Multiply every gradient by `factor`.
+	pub fn scale(&mut self, factor: f32) {
+		let weights = self.weights.iter_mut().flatten().flatten();
+		for gradient in weights.chain(self.biases.iter_mut().flatten()) {
+			*gradient *= factor;
+		}
+	}
+}
+
+impl LayerCheck {
+	pub fn passes(&self, tolerance: f32) -> bool {
+		self.error <= tolerance
+	}
+}
+
+/// Compare the gradients of the backward pass to central differences of the cost,
+/// nudging every weight and bias by `epsilon`.
+pub fn check_gradients(
+	network: &Network,
+	input: &[f32],
+	label: u8,
+	epsilon: f32,
+) -> Vec<LayerCheck> {
+	let analytic = network.gradients(input, label);
+	let mut probe = network.clone();
+	let layer_count = analytic.weights.len();
+
+	(0..layer_count)
+		.map(|layer| {
+			let mut parameters = 0;
+			let (mut difference, mut analytic_norm, mut numerical_norm) = (0., 0., 0.);
+
+			for neuron in 0..analytic.biases[layer].len() {
+				let weights = analytic.weights[layer][neuron].len();
+
+				// Every weight, followed by the bias.
+				for weight in (0..weights).map(Some).chain([None]) {
+					let expected = match weight {
+						Some(weight) => analytic.weights[layer][neuron][weight],
+						None => analytic.biases[layer][neuron],
+					};
+					let numerical =
+						numerical_gradient(&mut probe, input, label, epsilon, |network| {
+							let neuron = &mut neurons_mut(network, layer)[neuron];
+							match weight {
+								Some(weight) => &mut neuron.weights[weight],
+								None => &mut neuron.bias,
+							}
+						});
+
+					parameters += 1;
+					difference += (expected - numerical).powi(2);
+					analytic_norm += expected.powi(2);
+					numerical_norm += numerical.powi(2);
+				}
+			}
+
+			LayerCheck {
+				layer,
+				parameters,
+				error: relative_error(difference, analytic_norm, numerical_norm),
+			}
+		})
+		.collect()
+}
+
+/// The central difference of the cost when nudging the parameter returned by `parameter`.
+fn numerical_gradient(
+	network: &mut Network,
+	input: &[f32],
+	label: u8,
+	epsilon: f32,
+	parameter: impl Fn(&mut Network) -> &mut f32,
+) -> f32 {
+	let original = *parameter(network);
+
+	*parameter(network) = original + epsilon;
+	let cost_plus = network.cost(input, label);
+	*parameter(network) = original - epsilon;
+	let cost_minus = network.cost(input, label);
+	*parameter(network) = original;
+
+	(cost_plus - cost_minus) / (2. * epsilon)
+}
+
+/// Layers with smaller gradient norms are compared absolutely, the cost is computed
+/// in f32 so their numerical gradients are mostly rounding noise.
+const MIN_NORM: f32 = 1e-3;
+
+/// The norm of the difference relative to the norms of the gradients,
+/// given the sums of squares of the difference and both gradients.
+fn relative_error(difference: f32, analytic: f32, numerical: f32) -> f32 {
+	difference.sqrt() / (analytic.sqrt() + numerical.sqrt()).max(MIN_NORM)
+}
+
+fn neurons_mut(network: &mut Network, layer: usize) -> &mut Vec<Neuron> {
+	match network.activation_layers.get_mut(layer) {
+		Some(activation_layer) => &mut activation_layer.neurons,
+		None => &mut network.output_layer.neurons,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::{Rng, SeedableRng};
+	use rand_chacha::ChaCha8Rng;
+
+	use super::*;
+	use crate::utils::{activation_functions::Activation, loss_functions::Loss};
+
+	const EPSILON: f32 = 1e-2;
+	const TOLERANCE: f32 = 1e-2;
+
+	/// A small network with seeded random weights, and a random input for it.
+	fn setup(
+		shape: &[usize],
+		activation: Activation,
+		loss: Loss,
+		seed: u64,
+	) -> (Network, Vec<f32>) {
+		let mut rng = ChaCha8Rng::seed_from_u64(seed);
+		let mut network = Network::with_rng(0.1, shape, &mut rng).unwrap();
+		network.activation = activation;
+		network.loss = loss;
+
+		let input = (0..shape[0]).map(|_| rng.gen()).collect();
+		(network, input)
+	}
+
+	fn assert_gradients(shape: &[usize], activation: Activation, loss: Loss) {
+		for seed in 0..5 {
+			let (network, input) = setup(shape, activation, loss, seed);
+			let label = (seed as usize % shape[shape.len() - 1]) as u8;
+
+			let checks = check_gradients(&network, &input, label, EPSILON);
+			assert_eq!(checks.len(), shape.len() - 1);
+			for check in checks {
+				assert!(
+					check.passes(TOLERANCE),
+					"layer {} of {:?} with seed {}: {:?}",
+					check.layer,
+					shape,
+					seed,
+					check
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn sigmoid_cross_entropy() {
+		assert_gradients(&[6, 5, 4], Activation::Sigmoid, Loss::CrossEntropy);
+	}
+
+	#[test]
+	fn sigmoid_quadratic() {
+		assert_gradients(&[6, 5, 4], Activation::Sigmoid, Loss::Quadratic);
+	}
+
+	#[test]
+	fn tanh_cross_entropy() {
+		assert_gradients(&[6, 5, 4], Activation::Tanh, Loss::CrossEntropy);
+	}
+
+	#[test]
+	fn without_hidden_layers() {
+		assert_gradients(&[6, 4], Activation::Sigmoid, Loss::CrossEntropy);
+	}
+
+	#[test]
+	fn deep_network() {
+		assert_gradients(&[8, 6, 5, 4, 3], Activation::Sigmoid, Loss::CrossEntropy);
+	}
+
+	#[test]
+	fn dropout_gradients() {
+		let (mut network, input) = setup(&[6, 5, 4], Activation::Sigmoid, Loss::CrossEntropy, 9);
+		network.set_dropout(&[0.4]).unwrap();
+		let label = 2;
+
+		// The same seed drops the same neurons, whatever the weights are.
+		let cost = |network: &Network| {
+			let mut network = network.clone();
+			network.feed_forward_dropout(input.clone(), &mut ChaCha8Rng::seed_from_u64(1));
+			network.calculate_iteration_cost(label)
+		};
+
+		let mut dropped = network.clone();
+		dropped.feed_forward_dropout(input.clone(), &mut ChaCha8Rng::seed_from_u64(1));
+		let mask = dropped.activation_layers[0].mask.clone();
+		assert!(mask.contains(&0.) && mask.iter().any(|scale| *scale > 1.));
+
+		let activations = vec![
+			input.clone(),
+			dropped.activation_layers[0].activations(),
+			dropped.output_layer.activations(),
+		];
+		let gradients =
+			network.gradients_with_masks(&activations, std::slice::from_ref(&mask), label);
+
+		for layer in 0..2 {
+			for neuron in 0..network.layers()[layer].len() {
+				for weight in 0..network.layers()[layer][neuron].weights.len() {
+					let mut plus = network.clone();
+					neurons_mut(&mut plus, layer)[neuron].weights[weight] += EPSILON;
+					let mut minus = network.clone();
+					neurons_mut(&mut minus, layer)[neuron].weights[weight] -= EPSILON;
+
+					let numeric = (cost(&plus) - cost(&minus)) / (2. * EPSILON);
+					let analytic = gradients.weights[layer][neuron][weight];
+					assert!(
+						(numeric - analytic).abs() < TOLERANCE,
+						"weight {} of neuron {} in layer {}: {} vs. {}",
+						weight,
+						neuron,
+						layer,
+						numeric,
+						analytic
+					);
+				}
+			}
+		}
+
+		// Dropped neurons don't learn.
+		for (neuron, scale) in mask.iter().enumerate() {
+			if *scale == 0. {
+				assert!(gradients.weights[0][neuron]
+					.iter()
+					.all(|gradient| *gradient == 0.));
+				assert_eq!(gradients.biases[0][neuron], 0.);
+			}
+		}
+	}
+
+	#[test]
+	fn backpropagate_takes_a_gradient_step() {
+		let (mut network, input) = setup(&[6, 5, 4], Activation::Sigmoid, Loss::CrossEntropy, 7);
+		let gradients = network.gradients(&input, 2);
+
+		let mut expected = network.clone();
+		expected.apply_gradients(&gradients);
+
+		network.feed_forward(input);
+		network.backpropagate(2);
+
+		for (layer, expected_layer) in network.layers().iter().zip(expected.layers()) {
+			for (neuron, expected_neuron) in layer.iter().zip(expected_layer) {
+				assert_eq!(neuron.weights, expected_neuron.weights);
+				assert_eq!(neuron.bias, expected_neuron.bias);
+			}
+		}
+	}
+
+	#[test]
+	fn backpropagate_reduces_cost() {
+		let (mut network, input) = setup(&[6, 5, 4], Activation::Sigmoid, Loss::CrossEntropy, 3);
+		let before = network.cost(&input, 1);
+
+		network.feed_forward(input.clone());
+		network.backpropagate(1);
+
+		assert!(network.cost(&input, 1) < before);
+	}
+
+	#[test]
+	fn train_batch_takes_the_average_gradient_step() {
+		let (mut network, input) = setup(&[6, 5, 4], Activation::Sigmoid, Loss::CrossEntropy, 5);
+		let other: Vec<f32> = input.iter().rev().copied().collect();
+		let cost = (network.cost(&input, 1) + network.cost(&other, 3)) / 2.;
+
+		let mut gradients = network.gradients(&input, 1);
+		gradients.add(&network.gradients(&other, 3));
+		gradients.scale(0.5);
+		let mut expected = network.clone();
+		expected.apply_gradients(&gradients);
+
+		assert!((network.train_batch(&[input, other], &[1, 3]) - cost).abs() < 1e-6);
+		for (layer, expected_layer) in network.layers().iter().zip(expected.layers()) {
+			for (neuron, expected_neuron) in layer.iter().zip(expected_layer) {
+				assert_eq!(neuron.weights, expected_neuron.weights);
+				assert_eq!(neuron.bias, expected_neuron.bias);
+			}
+		}
+	}
+}
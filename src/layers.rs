@@ -1,3 +1,4 @@
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 use crate::neuron::Neuron;
@@ -10,6 +11,14 @@ pub struct InputLayer {
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct ActivationLayer {
 	pub neurons: Vec<Neuron>,
+	/// The probability of dropping every neuron while training.
+	/// It's stored in the model metadata, together with the rest of the network definition.
+	#[serde(skip)]
+	pub dropout: f32,
+	/// The dropout mask of the last training image: 0 for dropped neurons and the scale
+	/// of the kept ones for every neuron, or empty if no neurons were dropped.
+	#[serde(skip)]
+	pub mask: Vec<f32>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
@@ -27,28 +36,95 @@ impl InputLayer {
 
 impl ActivationLayer {
 	pub fn new(neurons_amount: usize, weights_amount: usize) -> ActivationLayer {
+		ActivationLayer::with_rng(neurons_amount, weights_amount, &mut rand::thread_rng())
+	}
+
+	pub fn with_rng(
+		neurons_amount: usize,
+		weights_amount: usize,
+		rng: &mut impl Rng,
+	) -> ActivationLayer {
 		let mut neurons_buf = Vec::with_capacity(neurons_amount);
 
 		for _ in 0..neurons_amount {
-			neurons_buf.push(Neuron::new(weights_amount));
+			neurons_buf.push(Neuron::with_rng(weights_amount, rng));
 		}
 
 		ActivationLayer {
 			neurons: neurons_buf,
+			..ActivationLayer::default()
+		}
+	}
+
+	/// Drop every neuron with the dropout rate of the layer, storing which ones in the mask.
+	/// Kept neurons are scaled by `1 / (1 - dropout)`, so the expected activations stay the
+	/// same and nothing needs to be scaled during inference.
+	pub fn sample_mask(&mut self, rng: &mut impl Rng) {
+		self.mask.clear();
+		if self.dropout <= 0. {
+			return;
+		}
+
+		let scale = 1. / (1. - self.dropout);
+		let dropout = self.dropout;
+		self.mask.extend((0..self.neurons.len()).map(|_| {
+			if rng.gen::<f32>() < dropout {
+				0.
+			} else {
+				scale
+			}
+		}));
+	}
+
+	/// The activations of the layer with the dropout mask applied.
+	pub fn masked_activations(&self) -> Vec<f32> {
+		let activations = self.activations();
+		if self.mask.is_empty() {
+			return activations;
 		}
+
+		activations
+			.iter()
+			.zip(&self.mask)
+			.map(|(activation, scale)| activation * scale)
+			.collect()
+	}
+
+	/// The current activation of every neuron in the layer.
+	pub fn activations(&self) -> Vec<f32> {
+		self.neurons
+			.iter()
+			.map(|neuron| neuron.activation)
+			.collect()
 	}
 }
 
 impl OutputLayer {
 	pub fn new(neurons_amount: usize, weights_amount: usize) -> OutputLayer {
+		OutputLayer::with_rng(neurons_amount, weights_amount, &mut rand::thread_rng())
+	}
+
+	pub fn with_rng(
+		neurons_amount: usize,
+		weights_amount: usize,
+		rng: &mut impl Rng,
+	) -> OutputLayer {
 		let mut neurons_buf = Vec::with_capacity(neurons_amount);
 
 		for _ in 0..neurons_amount {
-			neurons_buf.push(Neuron::new(weights_amount));
+			neurons_buf.push(Neuron::with_rng(weights_amount, rng));
 		}
 
 		OutputLayer {
 			neurons: neurons_buf,
 		}
 	}
+
+	/// The current activation of every neuron in the layer.
+	pub fn activations(&self) -> Vec<f32> {
+		self.neurons
+			.iter()
+			.map(|neuron| neuron.activation)
+			.collect()
+	}
 }
@@ -1,4 +1,22 @@
+pub mod bench;
+pub mod callback;
+pub mod checkpoint;
+pub mod compare;
+pub mod config;
+pub mod dataset;
+pub mod error;
+pub mod gradient;
 pub mod layers;
+pub mod logging;
+pub mod metrics;
+pub mod model;
 pub mod network;
 pub mod neuron;
+pub mod plot;
+pub mod predict;
+pub mod preprocessing;
+pub mod progress;
+pub mod server;
+pub mod stdio;
+pub mod tensorboard;
 pub mod utils;
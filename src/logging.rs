@@ -0,0 +1,277 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use clap::ArgAction;
+use log::LevelFilter;
+use log4rs::config::{Config, Deserializers, Logger, RawConfig};
+
+use crate::error::{Error, Result};
+
+/// The logging config used when no other is found, the same as `config/log4rs.yaml`.
+/// The `console` logger writes to stdout, and everything is written to `logs/main.log`.
+pub const DEFAULT_CONFIG: &str = include_str!("../config/log4rs.yaml");
+/// The logging config that is used instead of the built-in one if it exists.
+pub const CONFIG_PATH: &str = "config/log4rs.yaml";
+/// The environment variable that overrides the levels of the logging config.
+pub const ENV_VAR: &str = "RUST_LOG";
+/// The target of the logger that writes to the console.
+pub const CONSOLE_TARGET: &str = "console";
+
+/// Where the logging config comes from and how much is logged to the console.
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct LoggingOptions {
+	/// Logging config file in YAML, JSON or TOML, in the log4rs format.
+	/// Defaults to `config/log4rs.yaml` if it exists, and to a built-in config otherwise.
+	#[arg(long, global = true)]
+	pub log_config: Option<PathBuf>,
+	/// Log more to the console: `-v` for debug and `-vv` for trace messages.
+	#[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+	pub verbose: u8,
+	/// Log less to the console: `-q` for only warnings, `-qq` for only errors and `-qqq` for nothing.
+	#[arg(short, long, global = true, action = ArgAction::Count)]
+	pub quiet: u8,
+}
+
+/// A level set by the environment variable, for a target or, without one, for the root logger.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Directive {
+	pub target: Option<String>,
+	pub level: LevelFilter,
+}
+
+impl LoggingOptions {
+	/// The level of the console logger, or None to keep the level of the config.
+	pub fn console_level(&self) -> Option<LevelFilter> {
+		match (self.verbose, self.quiet) {
+			(0, 0) => None,
+			(1, _) => Some(LevelFilter::Debug),
+			(_, 0) => Some(LevelFilter::Trace),
+			(_, 1) => Some(LevelFilter::Warn),
+			(_, 2) => Some(LevelFilter::Error),
+			_ => Some(LevelFilter::Off),
+		}
+	}
+}
+
+/// Set up logging with the config of `options`.
+/// The levels are overridden by the `RUST_LOG` environment variable, and the console level by
+/// `--verbose` or `--quiet`.
+pub fn init(options: &LoggingOptions) -> Result<()> {
+	let directives = match std::env::var(ENV_VAR) {
+		Ok(spec) => parse_directives(&spec)?,
+		Err(_) => Vec::new(),
+	};
+	let config = build_config(
+		&load_raw_config(options)?,
+		&directives,
+		options.console_level(),
+	)?;
+
+	log4rs::init_config(config).map_err(|err| Error::Logging(err.to_string()))?;
+	Ok(())
+}
+
+/// Read the logging config of `options`, falling back to `config/log4rs.yaml` and then to the
+/// built-in config.
+pub fn load_raw_config(options: &LoggingOptions) -> Result<RawConfig> {
+	let path = match &options.log_config {
+		Some(path) => Some(path.as_path()),
+		None => Some(Path::new(CONFIG_PATH)).filter(|path| path.is_file()),
+	};
+
+	match path {
+		Some(path) => fs::read_to_string(path)
+			.map_err(|err| err.to_string())
+			.and_then(|contents| parse_raw_config(path, &contents))
+			.map_err(|err| Error::Logging(format!("{}: {}", path.display(), err))),
+		None => parse_raw_config(Path::new(CONFIG_PATH), DEFAULT_CONFIG)
+			.map_err(|err| Error::Logging(format!("built-in config: {}", err))),
+	}
+}
+
+/// Parse a logging config, picking the format from the extension. Anything but `.json` and
+/// `.toml` is YAML.
+fn parse_raw_config(path: &Path, contents: &str) -> Result<RawConfig, String> {
+	let extension = path
+		.extension()
+		.map(|extension| extension.to_string_lossy().to_lowercase());
+
+	match extension.as_deref() {
+		Some("json") => serde_json::from_str(contents).map_err(|err| err.to_string()),
+		Some("toml") => toml::from_str(contents).map_err(|err| err.to_string()),
+		_ => serde_yaml::from_str(contents).map_err(|err| err.to_string()),
+	}
+}
+
+/// Build the config, with the levels of the directives and the console level applied in order.
+pub fn build_config(
+	raw: &RawConfig,
+	directives: &[Directive],
+	console_level: Option<LevelFilter>,
+) -> Result<Config> {
+	let (appenders, errors) = raw.appenders_lossy(&Deserializers::default());
+	if !errors.is_empty() {
+		return Err(Error::Logging(errors.to_string()));
+	}
+
+	let mut root = raw.root();
+	let mut loggers = raw.loggers();
+	let console = console_level.map(|level| Directive {
+		target: Some(CONSOLE_TARGET.to_string()),
+		level,
+	});
+
+	for directive in directives.iter().chain(console.as_ref()) {
+		match &directive.target {
+			Some(target) => set_level(&mut loggers, target, directive.level),
+			None => root.set_level(directive.level),
+		}
+	}
+
+	Config::builder()
+		.appenders(appenders)
+		.loggers(loggers)
+		.build(root)
+		.map_err(|err| Error::Logging(err.to_string()))
+}
+
+/// Set the level of the logger of `target`, adding one that logs to the appenders of its
+/// parent if there is none.
+fn set_level(loggers: &mut Vec<Logger>, target: &str, level: LevelFilter) {
+	let logger = match loggers.iter().position(|logger| logger.name() == target) {
+		Some(index) => {
+			let logger = loggers.remove(index);
+			Logger::builder()
+				.appenders(logger.appenders().iter().cloned())
+				.additive(logger.additive())
+				.build(target, level)
+		}
+		None => Logger::builder().build(target, level),
+	};
+	loggers.push(logger);
+}
+
+/// Parse directives in the format of `RUST_LOG`, e.g. `warn,console=info,mnist_ai_rust::server=debug`.
+/// A level without a target sets the level of the root logger.
+pub fn parse_directives(spec: &str) -> Result<Vec<Directive>> {
+	spec.split(',')
+		.map(str::trim)
+		.filter(|directive| !directive.is_empty())
+		.map(|directive| {
+			let (target, level) = match directive.split_once('=') {
+				Some((target, level)) => (Some(target.trim().to_string()), level.trim()),
+				None => (None, directive),
+			};
+			let level = level
+				.parse()
+				.map_err(|_| Error::Logging(format!("invalid level `{}` in {}", level, ENV_VAR)))?;
+			Ok(Directive { target, level })
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn level(config: &Config, target: &str) -> Option<LevelFilter> {
+		config
+			.loggers()
+			.iter()
+			.find(|logger| logger.name() == target)
+			.map(|logger| logger.level())
+	}
+
+	#[test]
+	fn parses_directives() {
+		let directives =
+			parse_directives("warn, console=ERROR,mnist_ai_rust::server=debug,").unwrap();
+		assert_eq!(
+			directives,
+			[
+				Directive {
+					target: None,
+					level: LevelFilter::Warn,
+				},
+				Directive {
+					target: Some("console".to_string()),
+					level: LevelFilter::Error,
+				},
+				Directive {
+					target: Some("mnist_ai_rust::server".to_string()),
+					level: LevelFilter::Debug,
+				},
+			]
+		);
+
+		assert!(parse_directives("console=loud").is_err());
+	}
+
+	#[test]
+	fn parses_the_default_config() {
+		let raw = parse_raw_config(Path::new(CONFIG_PATH), DEFAULT_CONFIG).unwrap();
+		assert_eq!(raw.root().level(), LevelFilter::Trace);
+		assert_eq!(raw.loggers().len(), 1);
+		assert_eq!(raw.loggers()[0].name(), CONSOLE_TARGET);
+		assert_eq!(raw.loggers()[0].level(), LevelFilter::Info);
+	}
+
+	#[test]
+	fn overrides_levels() {
+		// Only a console appender, so building the config doesn't create a log file.
+		let raw = parse_raw_config(
+			Path::new("log4rs.json"),
+			r#"{
+				"appenders": { "stdout": { "kind": "console" } },
+				"root": { "level": "info", "appenders": ["stdout"] },
+				"loggers": { "console": { "level": "info", "appenders": ["stdout"], "additive": false } }
+			}"#,
+		)
+		.unwrap();
+
+		let config = build_config(&raw, &[], None).unwrap();
+		assert_eq!(config.root().level(), LevelFilter::Info);
+		assert_eq!(level(&config, CONSOLE_TARGET), Some(LevelFilter::Info));
+
+		let directives = parse_directives("error,console=trace,hyper=off").unwrap();
+		let config = build_config(&raw, &directives, Some(LevelFilter::Warn)).unwrap();
+		assert_eq!(config.root().level(), LevelFilter::Error);
+		// The console level of the flags wins over the environment variable.
+		assert_eq!(level(&config, CONSOLE_TARGET), Some(LevelFilter::Warn));
+		assert_eq!(level(&config, "hyper"), Some(LevelFilter::Off));
+
+		let console = config
+			.loggers()
+			.iter()
+			.find(|logger| logger.name() == CONSOLE_TARGET)
+			.unwrap();
+		assert_eq!(console.appenders(), ["stdout"]);
+		assert!(!console.additive());
+	}
+
+	#[test]
+	fn console_levels() {
+		let options = |verbose, quiet| LoggingOptions {
+			verbose,
+			quiet,
+			..LoggingOptions::default()
+		};
+
+		assert_eq!(options(0, 0).console_level(), None);
+		assert_eq!(options(1, 0).console_level(), Some(LevelFilter::Debug));
+		assert_eq!(options(3, 0).console_level(), Some(LevelFilter::Trace));
+		assert_eq!(options(0, 1).console_level(), Some(LevelFilter::Warn));
+		assert_eq!(options(0, 2).console_level(), Some(LevelFilter::Error));
+		assert_eq!(options(0, 3).console_level(), Some(LevelFilter::Off));
+
+		// Every flag changes what the default config shows on the console.
+		let raw = parse_raw_config(Path::new(CONFIG_PATH), DEFAULT_CONFIG).unwrap();
+		let console = raw.loggers()[0].level();
+		for (verbose, quiet) in [(1, 0), (2, 0), (0, 1), (0, 2), (0, 3)] {
+			let level = options(verbose, quiet).console_level().unwrap();
+			assert_ne!(level, console, "-v {} -q {}", verbose, quiet);
+		}
+	}
+}
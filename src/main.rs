@@ -1,51 +1,928 @@
-use clap::{Parser, ValueEnum};
-use mnist_ai_rust::network::Network;
+use std::{
+	net::SocketAddr,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
 
-#[derive(ValueEnum, Clone, Debug)]
-enum Mode {
-	Train,
-	Test,
-}
+use anyhow::Context;
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use log::{info, warn};
+use mnist_ai_rust::{
+	bench,
+	callback::Callback,
+	checkpoint::{Checkpoint, CheckpointOptions, TrainingState},
+	compare::{compare, evaluate_models},
+	config::{Dataset, ExperimentConfig, Optimizer, Regularization, MAX_SEED},
+	logging::{self, LoggingOptions},
+	metrics::{read_metrics, MetricsWriter},
+	model::{load_path, save_path, ModelFile},
+	network::Network,
+	plot::{plot_curves, COLORS},
+	predict::{load_image, Candidate},
+	preprocessing::Preprocessing,
+	progress::{Progress, ProgressCallback},
+	server::{serve, ServerOptions},
+	stdio,
+	tensorboard::TensorBoardWriter,
+};
+use rand::Rng;
+use serde::Serialize;
 
+/// The amount of pixels in an MNIST image.
+const INPUT_SIZE: usize = 784;
+/// The amount of digits an MNIST image can show.
+const OUTPUT_SIZE: usize = 10;
+const DEFAULT_SHAPE: [usize; 4] = [INPUT_SIZE, 16, 16, OUTPUT_SIZE];
+
+/// Returned when training stops on a signal, after a checkpoint was written.
+#[derive(Debug, thiserror::Error)]
+#[error("training was interrupted, continue it from the checkpoint with --resume")]
+struct Interrupted;
+
+/// Train and use neural networks that recognize handwritten digits.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-struct Args {
+#[command(override_usage = "mnist-ai-rust [OPTIONS] <COMMAND>")]
+struct Cli {
+	#[command(subcommand)]
+	command: Option<Command>,
+	#[command(flatten)]
+	legacy: LegacyArgs,
+	#[command(flatten)]
+	logging: LoggingOptions,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+	/// Train a network on the training dataset.
+	Train(Box<TrainArgs>),
+	/// Measure the accuracy of a network on the test dataset.
+	Test(TestArgs),
+	/// Compare networks against each other on the test dataset.
+	Compare(CompareArgs),
+	/// Show the metadata and weight statistics of a saved network.
+	Inspect(InspectArgs),
+	/// Convert a saved network to another format.
+	Convert(ConvertArgs),
+	/// Export the weights of every neuron as images to the `images` folder.
+	Export(ExportArgs),
+	/// Predict the digits shown in PNG or JPEG images.
+	Predict(PredictArgs),
+	/// Serve predictions over HTTP.
+	Serve(ServeArgs),
+	/// Measure how many images per second a network of a given shape trains and predicts.
+	Bench(BenchArgs),
+	/// Plot the cost and accuracy curves of metrics files written by `train --metrics`.
+	Plot(PlotArgs),
+}
+
+#[derive(Args, Debug)]
+struct TrainArgs {
+	/// Experiment config file in TOML, YAML or JSON.
+	/// The other flags override the values in the file.
+	#[arg(short, long, conflicts_with = "resume")]
+	config: Option<String>,
+	/// Network to continue training, a new network is created if omitted.
 	#[arg(short, long)]
-	mode: Mode,
-	#[arg(long, default_value_t = 100)]
-	iterations: usize,
-	#[arg(short, long, default_value_t = 0.1)]
-	learning_rate: f32,
-	#[arg(short, long, default_value = None)]
 	input: Option<String>,
-	#[arg(short, long, default_value = None)]
+	/// Where the trained network is saved.
+	/// The format is picked from the extension: `.json`, `.bin`, `.f16.bin`,
+	/// optionally followed by `.gz` for gzip compression.
+	#[arg(short, long)]
 	output: Option<String>,
+	/// The dataset to train on [default: mnist].
+	/// Every dataset except MNIST is generated, so it works without downloads.
+	#[arg(long, conflicts_with = "resume")]
+	dataset: Option<Dataset>,
+	/// The amount of dataset iterations to train for [default: 100].
+	#[arg(long, conflicts_with = "resume")]
+	iterations: Option<usize>,
+	/// [default: 0.1]
+	#[arg(short, long, conflicts_with = "resume")]
+	learning_rate: Option<f32>,
+	/// The amount of neurons in every hidden layer of a new network, e.g. `128,64`.
+	/// Networks loaded with `--input` or `--resume` keep their shape.
+	#[arg(long, value_delimiter = ',', conflicts_with_all = ["input", "resume"])]
+	hidden: Option<Vec<usize>>,
+	/// The dropout rate of every hidden layer while training, e.g. `0.2`, or one rate
+	/// per hidden layer, e.g. `0.5,0.2`. Networks loaded with `--input` keep their dropout
+	/// rates if omitted.
+	#[arg(long, value_delimiter = ',', conflicts_with = "resume")]
+	dropout: Option<Vec<f32>>,
+	/// Strength of the L1 penalty on the weights.
+	#[arg(long, conflicts_with = "resume")]
+	l1: Option<f32>,
+	/// Strength of the L2 penalty on the weights.
+	#[arg(long, conflicts_with = "resume")]
+	l2: Option<f32>,
+	/// Apply the L2 penalty as decoupled weight decay, like AdamW.
+	#[arg(long, default_value_t = false, conflicts_with = "resume")]
+	decoupled_weight_decay: bool,
+	/// The largest L2 norm the weights of every neuron may have.
+	#[arg(long, conflicts_with = "resume")]
+	max_norm: Option<f32>,
+	/// Export the weights of every neuron as images after training.
+	#[arg(short, long, default_value_t = false)]
+	generate_images: bool,
+	/// Where checkpoints are written during training.
+	#[arg(long, default_value = "checkpoint.json")]
+	checkpoint: String,
+	/// Write a checkpoint after every N dataset iterations.
+	/// A checkpoint is always written when training is interrupted.
+	#[arg(long)]
+	checkpoint_every: Option<usize>,
+	/// Continue training from a checkpoint, with the config stored in it.
+	#[arg(long, conflicts_with = "input")]
+	resume: Option<String>,
+	/// Shuffle the training images before every dataset iteration.
+	#[arg(long, default_value_t = false, conflicts_with = "resume")]
+	shuffle: bool,
+	/// Seed for the initial weights and the random number generator used during training.
+	#[arg(long, conflicts_with = "resume")]
+	seed: Option<u64>,
+	/// Write the metrics of every dataset iteration to a `.csv` or `.jsonl` file.
+	/// The network is then also tested after every dataset iteration.
+	#[arg(long)]
+	metrics: Option<String>,
+	/// Write TensorBoard event files to this folder, with the metrics, histograms of the
+	/// weights and gradients and misclassified images of every dataset iteration.
+	/// The network is then also tested after every dataset iteration.
+	#[arg(long)]
+	tensorboard: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct TestArgs {
+	/// The network to test, a new untrained network is tested if omitted.
+	model: Option<String>,
+	/// Export the weights of every neuron as images after testing.
 	#[arg(short, long, default_value_t = false)]
 	generate_images: bool,
 }
 
+#[derive(Args, Debug)]
+struct CompareArgs {
+	/// Networks to compare, the first one is used as the baseline.
+	#[arg(num_args = 2.., required = true)]
+	models: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct InspectArgs {
+	/// The network to inspect.
+	model: String,
+}
+
+#[derive(Args, Debug)]
+struct ConvertArgs {
+	/// The network to convert, in any format.
+	input: String,
+	/// Where the converted network is saved.
+	/// The format is picked from the extension: `.json`, `.bin`, `.f16.bin`,
+	/// optionally followed by `.gz` for gzip compression.
+	output: String,
+}
+
+#[derive(Args, Debug)]
+struct ExportArgs {
+	/// The network to export.
+	model: String,
+}
+
+#[derive(Args, Debug)]
+struct PredictArgs {
+	/// The network to predict with.
+	model: String,
+	/// Images to classify, they are converted to 28×28 grayscale.
+	#[arg(num_args = 1.., required_unless_present = "stdio")]
+	images: Vec<String>,
+	/// Print one JSON object per image instead of text.
+	#[arg(long, default_value_t = false)]
+	json: bool,
+	/// Read one JSON request per line from stdin, and write one JSON prediction per line to stdout.
+	/// Requests look like `{"id": 1, "pixels": [...]}` or `{"id": 1, "png": "<base64>"}`.
+	#[arg(long, default_value_t = false, conflicts_with_all = ["images", "json"])]
+	stdio: bool,
+	/// Set pixels darker than this value, between 0 and 1, to black before preprocessing.
+	#[arg(long)]
+	threshold: Option<f32>,
+	/// Straighten slanted digits.
+	#[arg(long, default_value_t = false)]
+	deskew: bool,
+}
+
+#[derive(Args, Debug)]
+struct ServeArgs {
+	/// The network to serve, it's reloaded when the file changes.
+	model: String,
+	#[arg(long, default_value = "127.0.0.1:8080")]
+	address: SocketAddr,
+	/// The most requests fed forward together.
+	#[arg(long, default_value_t = 32)]
+	max_batch: usize,
+	/// Milliseconds a request waits for other requests to share its forward pass.
+	#[arg(long, default_value_t = 2)]
+	batch_delay: u64,
+	/// Milliseconds between checks whether the network file changed.
+	#[arg(long, default_value_t = 1000)]
+	reload_interval: u64,
+}
+
+#[derive(Args, Debug)]
+struct BenchArgs {
+	/// The amount of neurons in every hidden layer, e.g. `128,64`.
+	#[arg(long, value_delimiter = ',', default_value = "16,16")]
+	hidden: Vec<usize>,
+	/// The dataset that decides the amount of inputs and outputs.
+	#[arg(long, default_value = "mnist")]
+	dataset: Dataset,
+	/// The amount of random images used for every measurement.
+	#[arg(long, default_value_t = 1000)]
+	samples: usize,
+	/// The amount of images in a mini-batch.
+	#[arg(long, default_value_t = 32)]
+	batch: usize,
+}
+
+#[derive(Args, Debug)]
+struct PlotArgs {
+	/// Metrics files to plot together, every file gets its own color.
+	#[arg(required = true)]
+	metrics: Vec<String>,
+	/// Where the PNG is saved.
+	#[arg(short, long, default_value = "curves.png")]
+	output: String,
+}
+
+/// The prediction for a single image, as printed with `predict --json`.
+#[derive(Serialize)]
+struct ImagePrediction<'a> {
+	file: &'a str,
+	digit: usize,
+	top: Vec<Candidate>,
+}
+
+// The flags used before subcommands existed, kept so `-m train` keeps working.
+// They can't be combined with a subcommand, but the logging flags can.
+#[derive(Args, Debug)]
+struct LegacyArgs {
+	#[arg(short, long, hide = true)]
+	mode: Option<Mode>,
+	#[arg(long, hide = true)]
+	iterations: Option<usize>,
+	#[arg(short, long, hide = true)]
+	learning_rate: Option<f32>,
+	#[arg(short, long, hide = true)]
+	input: Option<String>,
+	#[arg(short, long, hide = true)]
+	output: Option<String>,
+	#[arg(short, long, hide = true, default_value_t = false)]
+	generate_images: bool,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum Mode {
+	Train,
+	Test,
+}
+
+impl LegacyArgs {
+	fn is_empty(&self) -> bool {
+		self.mode.is_none()
+			&& self.iterations.is_none()
+			&& self.learning_rate.is_none()
+			&& self.input.is_none()
+			&& self.output.is_none()
+			&& !self.generate_images
+	}
+
+	fn into_command(self, mode: Mode) -> Command {
+		match mode {
+			Mode::Train => Command::Train(Box::new(TrainArgs {
+				config: None,
+				input: self.input,
+				output: self.output,
+				dataset: None,
+				iterations: self.iterations,
+				learning_rate: self.learning_rate,
+				hidden: None,
+				dropout: None,
+				l1: None,
+				l2: None,
+				decoupled_weight_decay: false,
+				max_norm: None,
+				generate_images: self.generate_images,
+				checkpoint: "checkpoint.json".to_string(),
+				checkpoint_every: None,
+				resume: None,
+				shuffle: false,
+				seed: None,
+				metrics: None,
+				tensorboard: None,
+			})),
+			Mode::Test => Command::Test(TestArgs {
+				model: self.input,
+				generate_images: self.generate_images,
+			}),
+		}
+	}
+}
+
 #[tokio::main]
-async fn main() {
-	let args = Args::parse();
+async fn main() -> anyhow::Result<()> {
+	let cli = Cli::parse();
 
-	log4rs::init_file("config/log4rs.yaml", Default::default()).unwrap();
+	logging::init(&cli.logging)?;
 
-	let mut network = Network::new(args.learning_rate, (784, 16, 16, 10));
+	let command = match (cli.command, cli.legacy.mode.clone()) {
+		(Some(_), _) if !cli.legacy.is_empty() => Cli::command()
+			.error(
+				ErrorKind::ArgumentConflict,
+				"the flags of a subcommand need to come after it",
+			)
+			.exit(),
+		(Some(command), _) => command,
+		(None, Some(mode)) => {
+			warn!(
+				target: "console",
+				"`--mode` is deprecated, use the `train` and `test` subcommands instead"
+			);
+			cli.legacy.into_command(mode)
+		}
+		(None, None) => Cli::command()
+			.error(ErrorKind::MissingSubcommand, "a subcommand is required")
+			.exit(),
+	};
 
-	if let Some(file) = args.input {
-		network.load_layers(file).unwrap();
+	match command {
+		Command::Train(args) => train(*args)?,
+		Command::Test(args) => test(args)?,
+		Command::Compare(args) => compare_models(&args.models)?,
+		Command::Inspect(args) => inspect_model(&args.model)?,
+		Command::Convert(args) => convert_model(&args.input, &args.output)?,
+		Command::Export(args) => export_images(&args.model)?,
+		Command::Predict(args) => predict(args)?,
+		Command::Serve(args) => serve_model(args).await?,
+		Command::Bench(args) => bench_network(args)?,
+		Command::Plot(args) => plot_metrics(&args.metrics, &args.output)?,
 	}
 
-	match args.mode {
-		Mode::Train => network.train(args.iterations + 1),
-		Mode::Test => network.test(),
+	Ok(())
+}
+
+fn train(args: TrainArgs) -> anyhow::Result<()> {
+	let (mut network, mut state) = match (&args.resume, &args.input) {
+		(Some(path), _) => {
+			let (network, state) = Network::resume(Checkpoint::read(load_path(path))?)?;
+			info!(
+				target: "console",
+				"Resuming training at dataset iteration {} (image {}) of {}",
+				state.iteration,
+				state.sample,
+				state.iterations
+			);
+			(network, state)
+		}
+		(None, input) => {
+			let mut config = resolve_config(&args)?;
+			let network = match input {
+				Some(file) => {
+					let mut network = Network::from_file(file)?;
+					network.learning_rate = config.learning_rate();
+					network.loss = config.loss;
+					network.regularization = config.regularization;
+
+					// The loaded network keeps its shape and activation function.
+					let shape = network.shape();
+					config.hidden = shape[1..shape.len() - 1].to_vec();
+					config.activation = network.activation;
+					if config.dropout.is_empty() {
+						config.dropout = network.dropout();
+					} else {
+						network.set_dropout(&config.dropout_rates(config.hidden.len())?)?;
+					}
+					network.config = Some(config.clone());
+					network
+				}
+				None => Network::from_config(
+					&config,
+					config.dataset.input_size(),
+					config.dataset.classes(),
+				)?,
+			};
+
+			info!("Experiment config:\n{}", config.to_toml()?);
+			info!(
+				target: "console",
+				"Training {:?} network for {} iterations with seed {}",
+				network.shape(),
+				config.iterations,
+				config.seed.unwrap_or_default()
+			);
+
+			(network, TrainingState::from_config(&config))
+		}
+	};
+
+	// Interrupted runs are continued from their checkpoint, so nothing is saved.
+	if !train_network(&mut network, &mut state, &args)? {
+		return Err(Interrupted.into());
 	}
 
 	if args.generate_images {
-		network.generate_images();
+		network.generate_images()?;
+	}
+
+	let output = args
+		.output
+		.or_else(|| network.config.as_ref()?.output.clone());
+	if let Some(file) = output {
+		network.save_layers(file)?;
+	}
+
+	Ok(())
+}
+
+/// The experiment config from `--config`, or the defaults, overridden by the other flags.
+/// A random seed is picked when none is given, so the saved config reproduces the run.
+fn resolve_config(args: &TrainArgs) -> anyhow::Result<ExperimentConfig> {
+	let mut config = match &args.config {
+		Some(path) => ExperimentConfig::read(path)?,
+		None => ExperimentConfig::default(),
+	};
+
+	if let Some(hidden) = &args.hidden {
+		config.hidden = hidden.clone();
+	}
+	if let Some(dataset) = args.dataset {
+		config.dataset = dataset;
+	}
+	if let Some(dropout) = &args.dropout {
+		config.dropout = dropout.clone();
+	}
+	if let Some(l1) = args.l1 {
+		config.regularization.l1 = l1;
+	}
+	if let Some(l2) = args.l2 {
+		config.regularization.l2 = l2;
+	}
+	if args.decoupled_weight_decay {
+		config.regularization.decoupled = true;
+	}
+	if let Some(max_norm) = args.max_norm {
+		config.regularization.max_norm = Some(max_norm);
+	}
+	if let Some(iterations) = args.iterations {
+		config.iterations = iterations;
+	}
+	if let Some(learning_rate) = args.learning_rate {
+		config.optimizer = Optimizer::Sgd { learning_rate };
+	}
+	if args.shuffle {
+		config.shuffle = true;
+	}
+	if let Some(seed) = args.seed {
+		config.seed = Some(seed);
+	}
+	if let Some(output) = &args.output {
+		config.output = Some(output.clone());
+	}
+
+	config
+		.seed
+		.get_or_insert_with(|| rand::thread_rng().gen_range(0..=MAX_SEED));
+	config.validate()?;
+	Ok(config)
+}
+
+fn test(args: TestArgs) -> anyhow::Result<()> {
+	let mut network = match args.model {
+		Some(file) => Network::from_file(file)?,
+		None => Network::new(0.1, &DEFAULT_SHAPE)?,
+	};
+
+	let total = network.dataset()?.test_labels.nrows();
+	let mut progress = Progress::new("Testing", total);
+	network.test_with(|evaluation| {
+		let cost = evaluation.costs.last().copied().unwrap_or_default();
+		progress.update(evaluation.len(), cost);
+	})?;
+
+	if args.generate_images {
+		network.generate_images()?;
+	}
+
+	Ok(())
+}
+
+/// Train the network until every dataset iteration is done, or until
+/// Ctrl-C or SIGTERM is received. Returns whether training completed.
+fn train_network(
+	network: &mut Network,
+	state: &mut TrainingState,
+	args: &TrainArgs,
+) -> anyhow::Result<bool> {
+	let checkpoints = CheckpointOptions {
+		path: save_path(&args.checkpoint),
+		every: args.checkpoint_every,
+	};
+
+	let mut callbacks: Vec<Box<dyn Callback>> = vec![Box::<ProgressCallback>::default()];
+	if let Some(path) = &args.metrics {
+		callbacks.push(Box::new(MetricsWriter::create(path, &state.history)?));
+	}
+	if let Some(log_dir) = &args.tensorboard {
+		let writer = TensorBoardWriter::create(log_dir)?;
+		info!(
+			target: "console",
+			"Writing TensorBoard events to {}",
+			writer.path().display()
+		);
+		callbacks.push(Box::new(writer));
+	}
+
+	let stop = Arc::new(AtomicBool::new(false));
+	let listener = tokio::spawn({
+		let stop = stop.clone();
+		async move {
+			shutdown_signal().await;
+			info!(target: "console", "Stopping training, writing checkpoint");
+			stop.store(true, Ordering::Relaxed);
+			// A second signal exits right away.
+			std::process::exit(shutdown_signal().await);
+		}
+	});
+
+	let completed = network.train_from(state, Some(&checkpoints), &mut callbacks, &stop);
+
+	// The signal handlers stay installed, so exit on the next signal like without them.
+	listener.abort();
+	tokio::spawn(async { std::process::exit(shutdown_signal().await) });
+
+	Ok(completed?)
+}
+
+/// Wait for Ctrl-C, or SIGTERM on unix.
+/// Returns the exit code of a process killed by the signal.
+async fn shutdown_signal() -> i32 {
+	cfg_if::cfg_if! {
+		if #[cfg(unix)] {
+			use tokio::signal::unix::{signal, SignalKind};
+
+			let mut terminate = signal(SignalKind::terminate()).expect("Error listening for SIGTERM");
+			tokio::select! {
+				_ = tokio::signal::ctrl_c() => 130,
+				_ = terminate.recv() => 143,
+			}
+		} else {
+			tokio::signal::ctrl_c().await.expect("Error listening for Ctrl-C");
+			130
+		}
+	}
+}
+
+fn compare_models(models: &[String]) -> anyhow::Result<()> {
+	let evaluations = evaluate_models(models)?;
+
+	for model in evaluations.iter() {
+		let (lower, upper) = model.accuracy_interval;
+		info!(
+			target: "console",
+			"{} – Accuracy: {:.2}% (95% CI {:.2}% – {:.2}%), avg. cost: {}",
+			model.name,
+			model.evaluation.accuracy(),
+			lower,
+			upper,
+			model.evaluation.avg_cost()
+		);
+	}
+
+	for comparison in compare(&evaluations)? {
+		let (lower, upper) = comparison.delta_interval;
+		let mcnemar = &comparison.mcnemar;
+		info!(
+			target: "console",
+			"{} vs. {} – Accuracy delta: {:+.2}% (95% CI {:+.2}% – {:+.2}%), McNemar p-value: {:.4} ({} vs. {} discordant)",
+			comparison.candidate,
+			comparison.baseline,
+			comparison.accuracy_delta,
+			lower,
+			upper,
+			mcnemar.p_value,
+			mcnemar.candidate_only,
+			mcnemar.baseline_only
+		);
+
+		info!(
+			target: "console",
+			"{} images where the models disagree:",
+			comparison.disagreements.len()
+		);
+		for disagreement in comparison.disagreements.iter() {
+			info!(
+				target: "console",
+				"Image {}: label {}, {} guessed {:?}, {} guessed {:?}",
+				disagreement.index,
+				disagreement.label,
+				comparison.baseline,
+				disagreement.baseline,
+				comparison.candidate,
+				disagreement.candidate
+			);
+		}
+	}
+
+	Ok(())
+}
+
+fn convert_model(input: &str, output: &str) -> anyhow::Result<()> {
+	let model = ModelFile::read(load_path(input))?;
+	model.write(save_path(output))?;
+
+	info!(target: "console", "Converted network {} to {}", input, output);
+	Ok(())
+}
+
+fn predict(args: PredictArgs) -> anyhow::Result<()> {
+	let network = Network::from_file(&args.model)?;
+	if network.shape()[0] != INPUT_SIZE {
+		anyhow::bail!(
+			"network {} expects {} inputs, images have {} pixels",
+			args.model,
+			network.shape()[0],
+			INPUT_SIZE
+		);
+	}
+
+	let preprocessing = Preprocessing {
+		threshold: args.threshold,
+		deskew: args.deskew,
+	};
+
+	if args.stdio {
+		stdio::run(
+			&network,
+			&preprocessing,
+			std::io::stdin().lock(),
+			std::io::stdout().lock(),
+		)?;
+		return Ok(());
+	}
+
+	for file in args.images.iter() {
+		let input = load_image(file, &preprocessing)
+			.with_context(|| format!("could not load image {}", file))?;
+		let prediction = network.predict(&input);
+		let top = prediction.top(3);
+
+		if args.json {
+			let output = ImagePrediction {
+				file,
+				digit: prediction.digit,
+				top,
+			};
+			println!("{}", serde_json::to_string(&output)?);
+		} else {
+			let top: Vec<String> = top
+				.iter()
+				.map(|candidate| {
+					format!("{} ({:.2}%)", candidate.digit, candidate.probability * 100.)
+				})
+				.collect();
+			info!(
+				target: "console",
+				"{}: {} – top 3: {}",
+				file,
+				prediction.digit,
+				top.join(", ")
+			);
+		}
+	}
+
+	Ok(())
+}
+
+async fn serve_model(args: ServeArgs) -> anyhow::Result<()> {
+	let options = ServerOptions {
+		model: args.model,
+		address: args.address,
+		max_batch: args.max_batch,
+		batch_delay: Duration::from_millis(args.batch_delay),
+		reload_interval: Duration::from_millis(args.reload_interval),
+	};
+
+	Ok(serve(options, async {
+		shutdown_signal().await;
+	})
+	.await?)
+}
+
+fn bench_network(args: BenchArgs) -> anyhow::Result<()> {
+	let mut shape = vec![args.dataset.input_size()];
+	shape.extend_from_slice(&args.hidden);
+	shape.push(args.dataset.classes());
+
+	info!(
+		target: "console",
+		"Benchmarking {:?} network on {} images",
+		shape,
+		args.samples
+	);
+	let throughput = bench::measure(&shape, args.samples, args.batch)?;
+
+	info!(target: "console", "Forward: {:.0} images/s", throughput.forward);
+	info!(
+		target: "console",
+		"Forward and backward: {:.0} images/s",
+		throughput.train
+	);
+	info!(
+		target: "console",
+		"Mini-batches of {}: {:.0} images/s",
+		args.batch,
+		throughput.batch_train
+	);
+	Ok(())
+}
+
+fn plot_metrics(paths: &[String], output: &str) -> anyhow::Result<()> {
+	let runs = paths
+		.iter()
+		.map(|path| read_metrics(path).with_context(|| format!("could not read {}", path)))
+		.collect::<anyhow::Result<Vec<_>>>()?;
+
+	let plot = plot_curves(&runs);
+	plot.image.save(output)?;
+
+	info!(target: "console", "Saved training curves to {}", output);
+	info!(
+		target: "console",
+		"Cost on top from 0 to {}, accuracy below from 0% to 100%, dataset iterations {} to {}",
+		plot.max_cost,
+		plot.iterations.0,
+		plot.iterations.1
+	);
+	info!(target: "console", "Solid lines are training, dashed lines are validation");
+	for (path, (color, _)) in paths.iter().zip(COLORS.iter().cycle()) {
+		info!(target: "console", "{}: {}", color, path);
+	}
+	Ok(())
+}
+
+fn inspect_model(path: &str) -> anyhow::Result<()> {
+	let model = ModelFile::read(load_path(path))?;
+	let metadata = &model.metadata;
+
+	let layers = model.layers();
+	let parameters: usize = layers
+		.iter()
+		.flat_map(|neurons| neurons.iter())
+		.map(|neuron| neuron.weights.len() + 1)
+		.sum();
+
+	info!(target: "console", "Network {} (format version {})", path, model.version);
+	info!(target: "console", "Shape: {:?} ({} parameters)", metadata.shape, parameters);
+	info!(target: "console", "Activation: {:?}", metadata.activation);
+	if !metadata.dropout.is_empty() {
+		info!(target: "console", "Dropout: {:?}", metadata.dropout);
+	}
+	let regularization = metadata.config.as_ref().map(|config| config.regularization);
+	if let Some(regularization) = regularization.filter(Regularization::is_enabled) {
+		info!(target: "console", "Regularization: {:?}", regularization);
+	}
+	info!(target: "console", "Learning rate: {}", metadata.learning_rate);
+	info!(target: "console", "Dataset: {}", metadata.dataset);
+	info!(target: "console", "Epochs: {}", display_option(metadata.epochs));
+	info!(
+		target: "console",
+		"Training accuracy: {}",
+		display_option(metadata.training_accuracy.map(|accuracy| format!("{}%", accuracy)))
+	);
+	info!(
+		target: "console",
+		"Test accuracy: {}",
+		display_option(metadata.accuracy.map(|accuracy| format!("{}%", accuracy)))
+	);
+
+	for (index, neurons) in layers.iter().enumerate() {
+		let name = match index + 1 == layers.len() {
+			true => "Output layer".to_string(),
+			false => format!("Activation layer {}", index + 1),
+		};
+		let weights: Vec<f32> = neurons
+			.iter()
+			.flat_map(|neuron| neuron.weights.iter().copied())
+			.collect();
+		let biases: Vec<f32> = neurons.iter().map(|neuron| neuron.bias).collect();
+		let (weight_min, weight_max, weight_mean) = statistics(&weights);
+		let (bias_min, bias_max, bias_mean) = statistics(&biases);
+
+		info!(
+			target: "console",
+			"{}: {} neurons – weights min {:.4}, max {:.4}, mean {:.4} – biases min {:.4}, max {:.4}, mean {:.4}",
+			name,
+			neurons.len(),
+			weight_min,
+			weight_max,
+			weight_mean,
+			bias_min,
+			bias_max,
+			bias_mean
+		);
+	}
+
+	Ok(())
+}
+
+fn export_images(path: &str) -> anyhow::Result<()> {
+	let network = Network::from_file(path)?;
+	network.generate_images()?;
+
+	info!(target: "console", "Exported neuron images of {} to the images folder", path);
+	Ok(())
+}
+
+fn display_option(value: Option<impl ToString>) -> String {
+	value
+		.map(|value| value.to_string())
+		.unwrap_or_else(|| "unknown".to_string())
+}
+
+/// The minimum, maximum and mean of `values`.
+fn statistics(values: &[f32]) -> (f32, f32, f32) {
+	let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+	let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+	let mean = values.iter().sum::<f32>() / values.len() as f32;
+
+	(min, max, mean)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn train_args(args: &[&str]) -> TrainArgs {
+		let cli = Cli::try_parse_from(["mnist-ai-rust", "train"].iter().chain(args)).unwrap();
+		match cli.command {
+			Some(Command::Train(args)) => *args,
+			command => panic!("expected the train command, got {:?}", command),
+		}
+	}
+
+	#[test]
+	fn flags_override_the_config_file() {
+		let path =
+			std::env::temp_dir().join(format!("mnist-ai-resolve-{}.toml", std::process::id()));
+		std::fs::write(
+			&path,
+			"dataset = \"blobs\"\nhidden = [8]\niterations = 3\nseed = 7\n\n\
+			[optimizer]\nkind = \"sgd\"\nlearning_rate = 0.05\n\n\
+			[regularization]\nl2 = 0.01\n",
+		)
+		.unwrap();
+		let config_path = path.to_str().unwrap();
+
+		let config = resolve_config(&train_args(&["--config", config_path])).unwrap();
+		assert_eq!(config.dataset, Dataset::Blobs);
+		assert_eq!(config.iterations, 3);
+		assert_eq!(config.seed, Some(7));
+
+		let config = resolve_config(&train_args(&[
+			"--config",
+			config_path,
+			"--iterations",
+			"5",
+			"--learning-rate",
+			"0.2",
+			"--hidden",
+			"4,4",
+			"--l1",
+			"0.001",
+			"--seed",
+			"9",
+		]))
+		.unwrap();
+		let invalid = resolve_config(&train_args(&["--config", config_path, "--l2=-1"]));
+		let _ = std::fs::remove_file(&path);
+
+		assert_eq!(config.dataset, Dataset::Blobs);
+		assert_eq!(config.iterations, 5);
+		assert_eq!(config.learning_rate(), 0.2);
+		assert_eq!(config.hidden, [4, 4]);
+		assert_eq!(config.seed, Some(9));
+		// Flags only replace the values they set.
+		assert_eq!(config.regularization.l1, 0.001);
+		assert_eq!(config.regularization.l2, 0.01);
+		assert!(invalid.is_err());
 	}
 
-	if let Some(file) = args.output {
-		network.save_layers(file).unwrap();
+	#[test]
+	fn a_seed_is_always_picked() {
+		let config = resolve_config(&train_args(&["--dataset", "xor"])).unwrap();
+		assert_eq!(config.dataset, Dataset::Xor);
+		assert!(config.seed.is_some_and(|seed| seed <= MAX_SEED));
 	}
 }
@@ -0,0 +1,236 @@
+use std::{
+	fs::{self, File},
+	io::{BufRead, BufReader, BufWriter, Write},
+	path::Path,
+	str::FromStr,
+};
+
+use crate::{
+	callback::{Callback, Control},
+	checkpoint::EpochMetrics,
+	error::{Error, Result},
+	network::Network,
+};
+
+/// The columns of a CSV metrics file, in the order they are written.
+const CSV_COLUMNS: [&str; 10] = [
+	"iteration",
+	"accuracy",
+	"correct",
+	"avg_cost",
+	"validation_accuracy",
+	"validation_cost",
+	"learning_rate",
+	"seconds",
+	"gradient_norm",
+	"regularization_cost",
+];
+
+/// How metrics are written, one record per dataset iteration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricsFormat {
+	Csv,
+	/// A JSON object on every line.
+	Jsonl,
+}
+
+/// Writes the metrics of every dataset iteration to a file while training.
+pub struct MetricsWriter {
+	file: BufWriter<File>,
+	format: MetricsFormat,
+}
+
+impl MetricsFormat {
+	/// Pick the format from the file extension, `.csv` is CSV and everything else is JSON lines.
+	pub fn from_path(path: impl AsRef<Path>) -> MetricsFormat {
+		let extension = path
+			.as_ref()
+			.extension()
+			.map(|extension| extension.to_string_lossy().to_lowercase());
+
+		match extension.as_deref() {
+			Some("csv") => MetricsFormat::Csv,
+			_ => MetricsFormat::Jsonl,
+		}
+	}
+}
+
+impl MetricsWriter {
+	/// Create a metrics file, replacing an existing one, and write the metrics in `history` to it.
+	/// Resumed runs pass the history of their checkpoint, so the file covers the whole run.
+	pub fn create(path: impl AsRef<Path>, history: &[EpochMetrics]) -> Result<MetricsWriter> {
+		let path = path.as_ref();
+		let parent = path
+			.parent()
+			.filter(|parent| !parent.as_os_str().is_empty());
+		if let Some(parent) = parent {
+			fs::create_dir_all(parent)?;
+		}
+
+		let format = MetricsFormat::from_path(path);
+		let mut file = BufWriter::new(File::create(path)?);
+		if format == MetricsFormat::Csv {
+			writeln!(file, "{}", CSV_COLUMNS.join(","))?;
+		}
+
+		let mut writer = MetricsWriter { file, format };
+		for metrics in history {
+			writer.write(metrics)?;
+		}
+		writer.file.flush()?;
+		Ok(writer)
+	}
+
+	/// Write the metrics of a dataset iteration.
+	/// The file is flushed, so it can be read while training continues.
+	pub fn write(&mut self, metrics: &EpochMetrics) -> Result<()> {
+		match self.format {
+			MetricsFormat::Csv => writeln!(self.file, "{}", csv_record(metrics))?,
+			MetricsFormat::Jsonl => {
+				serde_json::to_writer(&mut self.file, metrics)?;
+				writeln!(self.file)?;
+			}
+		}
+
+		self.file.flush()?;
+		Ok(())
+	}
+}
+
+/// Writes every dataset iteration, including the test metrics.
+impl Callback for MetricsWriter {
+	fn evaluates(&self) -> bool {
+		true
+	}
+
+	fn on_epoch_end(&mut self, _: &mut Network, metrics: &EpochMetrics) -> Result<Control> {
+		self.write(metrics)?;
+		Ok(Control::Continue)
+	}
+}
+
+/// Read a metrics file written by a `MetricsWriter`.
+pub fn read_metrics(path: impl AsRef<Path>) -> Result<Vec<EpochMetrics>> {
+	let path = path.as_ref();
+	let mut lines = BufReader::new(File::open(path)?).lines();
+
+	match MetricsFormat::from_path(path) {
+		MetricsFormat::Csv => {
+			let header = lines.next().transpose()?.unwrap_or_default();
+			let columns: Vec<&str> = header.split(',').map(str::trim).collect();
+
+			lines
+				.filter(|line| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+				.map(|line| parse_csv_record(&columns, &line?))
+				.collect()
+		}
+		MetricsFormat::Jsonl => lines
+			.filter(|line| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+			.map(|line| Ok(serde_json::from_str(&line?)?))
+			.collect(),
+	}
+}
+
+fn csv_record(metrics: &EpochMetrics) -> String {
+	let optional = |value: Option<f32>| value.map(|value| value.to_string()).unwrap_or_default();
+
+	[
+		metrics.iteration.to_string(),
+		metrics.accuracy.to_string(),
+		metrics.correct.to_string(),
+		metrics.avg_cost.to_string(),
+		optional(metrics.validation_accuracy),
+		optional(metrics.validation_cost),
+		metrics.learning_rate.to_string(),
+		metrics.seconds.to_string(),
+		metrics.gradient_norm.to_string(),
+		metrics.regularization_cost.to_string(),
+	]
+	.join(",")
+}
+
+/// Parse a CSV record, using the header to find the columns.
+/// Missing columns and empty values use the defaults of `EpochMetrics`.
+fn parse_csv_record(columns: &[&str], line: &str) -> Result<EpochMetrics> {
+	let values: Vec<&str> = line.split(',').map(str::trim).collect();
+
+	Ok(EpochMetrics {
+		iteration: parse_field(columns, &values, "iteration")?.unwrap_or_default(),
+		accuracy: parse_field(columns, &values, "accuracy")?.unwrap_or_default(),
+		correct: parse_field(columns, &values, "correct")?.unwrap_or_default(),
+		avg_cost: parse_field(columns, &values, "avg_cost")?.unwrap_or_default(),
+		regularization_cost: parse_field(columns, &values, "regularization_cost")?
+			.unwrap_or_default(),
+		validation_accuracy: parse_field(columns, &values, "validation_accuracy")?,
+		validation_cost: parse_field(columns, &values, "validation_cost")?,
+		learning_rate: parse_field(columns, &values, "learning_rate")?.unwrap_or_default(),
+		seconds: parse_field(columns, &values, "seconds")?.unwrap_or_default(),
+		gradient_norm: parse_field(columns, &values, "gradient_norm")?.unwrap_or_default(),
+	})
+}
+
+/// The value of the column `name`, or None if there is no such column or it's empty.
+fn parse_field<T: FromStr>(columns: &[&str], values: &[&str], name: &str) -> Result<Option<T>> {
+	let value = columns
+		.iter()
+		.position(|column| *column == name)
+		.and_then(|index| values.get(index))
+		.filter(|value| !value.is_empty());
+
+	value
+		.map(|value| {
+			value
+				.parse()
+				.map_err(|_| Error::Metrics(format!("invalid {} `{}`", name, value)))
+		})
+		.transpose()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn history() -> Vec<EpochMetrics> {
+		(0..3)
+			.map(|iteration| EpochMetrics {
+				iteration,
+				accuracy: 50. + iteration as f32,
+				correct: 100 + iteration,
+				avg_cost: 1.5 / (iteration + 1) as f32,
+				regularization_cost: 0.125,
+				validation_accuracy: (iteration > 0).then_some(60.),
+				validation_cost: (iteration > 0).then_some(0.75),
+				learning_rate: 0.1,
+				seconds: 2.5,
+				gradient_norm: 0.25 * iteration as f32,
+			})
+			.collect()
+	}
+
+	fn round_trip(extension: &str) {
+		let path = std::env::temp_dir().join(format!(
+			"mnist-ai-metrics-{}.{}",
+			std::process::id(),
+			extension
+		));
+		let history = history();
+
+		let mut writer = MetricsWriter::create(&path, &history[..2]).unwrap();
+		writer.write(&history[2]).unwrap();
+		drop(writer);
+
+		let read = read_metrics(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+		assert_eq!(read, history);
+	}
+
+	#[test]
+	fn csv_round_trip() {
+		round_trip("csv");
+	}
+
+	#[test]
+	fn jsonl_round_trip() {
+		round_trip("jsonl");
+	}
+}
@@ -0,0 +1,851 @@
+use std::{
+	fs,
+	io::{Read, Write},
+	path::{Path, PathBuf},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use half::f16;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::{
+	config::ExperimentConfig,
+	layers::{ActivationLayer, OutputLayer},
+	neuron::Neuron,
+	utils::{activation_functions::Activation, write_atomic},
+};
+
+/// The newest model file format this version of the crate can read and write.
+///
+/// Version history:
+/// - `0`: The unversioned format, containing only the learning rate and the layers.
+/// - `1`: Layers wrapped in an envelope together with a version and metadata.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Magic bytes at the start of every binary model file.
+const BINARY_MAGIC: &[u8; 4] = b"MNAI";
+/// Magic bytes at the start of every gzip stream.
+const GZIP_MAGIC: &[u8; 2] = &[0x1f, 0x8b];
+
+/// The amount of bits used for every weight and bias in a binary model file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Precision {
+	Single,
+	Half,
+}
+
+impl Precision {
+	/// The amount of bytes of every weight and bias.
+	fn size(self) -> usize {
+		match self {
+			Precision::Single => 4,
+			Precision::Half => 2,
+		}
+	}
+}
+
+/// How the model is encoded before it is (optionally) compressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+	Json,
+	Binary(Precision),
+}
+
+/// The on-disk format of a model file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ModelFormat {
+	pub encoding: Encoding,
+	/// Whether the encoded model is gzip compressed.
+	pub compressed: bool,
+}
+
+/// Errors that can happen while reading a model file.
+#[derive(Debug, Error)]
+pub enum ModelError {
+	#[error(
+		"model file uses format version {found}, but only versions up to {supported} are supported, try updating mnist-ai-rust"
+	)]
+	UnsupportedVersion { found: u32, supported: u32 },
+	#[error("model file has an invalid format version: {0}")]
+	InvalidVersion(Value),
+	#[error("could not parse model file: {0}")]
+	Parse(#[from] serde_json::Error),
+	#[error("could not read or write model file: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("invalid binary model file: {0}")]
+	InvalidBinary(&'static str),
+	#[error("model file has {found} layers, but the network expects {expected}")]
+	LayerCount { expected: usize, found: usize },
+	#[error("model file has an input size of {found}, but the network expects {expected}")]
+	InputSize { expected: usize, found: usize },
+	#[error("{layer} has {found} neurons, but the network expects {expected}")]
+	NeuronCount {
+		layer: String,
+		expected: usize,
+		found: usize,
+	},
+	#[error("neuron {neuron} in {layer} has {found} weights, but the network expects {expected}")]
+	WeightCount {
+		layer: String,
+		neuron: usize,
+		expected: usize,
+		found: usize,
+	},
+	#[error("invalid dropout: {0}")]
+	InvalidDropout(String),
+	#[error("neuron {neuron} in {layer} has a non-finite {parameter}")]
+	NonFinite {
+		layer: String,
+		neuron: usize,
+		parameter: &'static str,
+	},
+}
+
+/// Information about how a model was created.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ModelMetadata {
+	/// The amount of inputs the first layer expects.
+	pub input_size: usize,
+	/// The amount of neurons in every layer, including the input layer.
+	pub shape: Vec<usize>,
+	/// The activation function of the activation layers,
+	/// the output layer always uses sigmoid.
+	pub activation: Activation,
+	pub learning_rate: f32,
+	/// The amount of dataset iterations the model has been trained for,
+	/// or `None` if it is unknown.
+	pub epochs: Option<usize>,
+	/// The name of the dataset the model was trained on.
+	pub dataset: String,
+	/// Accuracy in percent on the training dataset after the last epoch.
+	pub training_accuracy: Option<f32>,
+	/// Accuracy in percent on the test dataset, if the model has been tested.
+	pub accuracy: Option<f32>,
+	/// The dropout rate of every activation layer while training,
+	/// or empty if the network doesn't use dropout.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub dropout: Vec<f32>,
+	/// The resolved experiment config the model was trained with.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub config: Option<ExperimentConfig>,
+}
+
+/// A model as it is stored on disk.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ModelFile {
+	pub version: u32,
+	pub metadata: ModelMetadata,
+	pub activation_layers: Vec<ActivationLayer>,
+	pub output_layer: OutputLayer,
+}
+
+/// The unversioned format written before the model envelope existed.
+#[derive(Deserialize)]
+struct LegacyModelFile {
+	learning_rate: f32,
+	activation_layers: Vec<ActivationLayer>,
+	output_layer: OutputLayer,
+}
+
+impl ModelFile {
+	pub fn new(
+		metadata: ModelMetadata,
+		activation_layers: Vec<ActivationLayer>,
+		output_layer: OutputLayer,
+	) -> ModelFile {
+		ModelFile {
+			version: FORMAT_VERSION,
+			metadata,
+			activation_layers,
+			output_layer,
+		}
+	}
+
+	/// Parse a model file of any supported format version,
+	/// migrating older versions to the current one.
+	pub fn from_json(json: &str) -> Result<ModelFile, ModelError> {
+		let value: Value = serde_json::from_str(json)?;
+
+		let version = match value.get("version") {
+			None => 0,
+			Some(version) => version
+				.as_u64()
+				.and_then(|version| u32::try_from(version).ok())
+				.ok_or_else(|| ModelError::InvalidVersion(version.clone()))?,
+		};
+
+		match version {
+			0 => Ok(serde_json::from_value::<LegacyModelFile>(value)?.migrate()),
+			FORMAT_VERSION => Ok(serde_json::from_value(value)?),
+			found => Err(ModelError::UnsupportedVersion {
+				found,
+				supported: FORMAT_VERSION,
+			}),
+		}
+	}
+
+	/// The neurons of every layer after the input layer, including the output layer.
+	pub fn layers(&self) -> Vec<&[Neuron]> {
+		let mut layers: Vec<&[Neuron]> = self
+			.activation_layers
+			.iter()
+			.map(|layer| layer.neurons.as_slice())
+			.collect();
+		layers.push(&self.output_layer.neurons);
+		layers
+	}
+
+	/// The amount of neurons in every layer, including the input layer,
+	/// derived from the layers themselves.
+	pub fn shape(&self) -> Vec<usize> {
+		let layers = self.layers();
+		let input_size = layers[0]
+			.first()
+			.map(|neuron| neuron.weights.len())
+			.unwrap_or_default();
+
+		let mut shape = vec![input_size];
+		shape.extend(layers.iter().map(|neurons| neurons.len()));
+		shape
+	}
+
+	/// Check that the layers and the input size match `shape`, the amount of neurons in every layer
+	/// including the input layer, that all weights and biases are finite
+	/// and that there is a valid dropout rate for every activation layer.
+	pub fn validate(&self, shape: &[usize]) -> Result<(), ModelError> {
+		check_dropout(&self.metadata.dropout, self.activation_layers.len())
+			.map_err(ModelError::InvalidDropout)?;
+
+		let layers = self.layers();
+		if layers.len() + 1 != shape.len() {
+			return Err(ModelError::LayerCount {
+				expected: shape.len(),
+				found: layers.len() + 1,
+			});
+		}
+
+		for (index, neurons) in layers.iter().enumerate() {
+			let layer = layer_name(index, layers.len());
+			let expected_weights = shape.get(index).copied().unwrap_or_default();
+			let expected_neurons = shape.get(index + 1).copied().unwrap_or_default();
+
+			if neurons.len() != expected_neurons {
+				return Err(ModelError::NeuronCount {
+					layer: layer.clone(),
+					expected: expected_neurons,
+					found: neurons.len(),
+				});
+			}
+
+			for (neuron_index, neuron) in neurons.iter().enumerate() {
+				if neuron.weights.len() != expected_weights {
+					return Err(ModelError::WeightCount {
+						layer: layer.clone(),
+						neuron: neuron_index,
+						expected: expected_weights,
+						found: neuron.weights.len(),
+					});
+				}
+
+				let parameter = if !neuron.bias.is_finite() {
+					Some("bias")
+				} else if !neuron.weights.iter().all(|weight| weight.is_finite()) {
+					Some("weight")
+				} else {
+					None
+				};
+
+				if let Some(parameter) = parameter {
+					return Err(ModelError::NonFinite {
+						layer: layer.clone(),
+						neuron: neuron_index,
+						parameter,
+					});
+				}
+			}
+		}
+
+		if self.metadata.input_size != shape[0] {
+			return Err(ModelError::InputSize {
+				expected: shape[0],
+				found: self.metadata.input_size,
+			});
+		}
+
+		Ok(())
+	}
+
+	pub fn to_json(&self) -> Result<String, ModelError> {
+		Ok(serde_json::to_string(self)?)
+	}
+
+	/// Encode the model in the compact binary format.
+	///
+	/// All numbers are little-endian. The file starts with a header of the magic
+	/// bytes `MNAI`, the format version (`u32`), the precision (`u8`, `0` for f32
+	/// and `1` for f16), three reserved bytes and the length of the JSON encoded
+	/// metadata (`u32`) followed by the metadata itself. After the header follows
+	/// the amount of layers (`u32`), and for every layer the amount of neurons
+	/// (`u32`), the amount of weights per neuron (`u32`), all weights neuron by
+	/// neuron and finally all biases.
+	pub fn to_binary(&self, precision: Precision) -> Result<Vec<u8>, ModelError> {
+		let metadata = serde_json::to_vec(&self.metadata)?;
+		let mut buffer = Vec::new();
+
+		buffer.extend_from_slice(BINARY_MAGIC);
+		buffer.extend_from_slice(&self.version.to_le_bytes());
+		buffer.push(match precision {
+			Precision::Single => 0,
+			Precision::Half => 1,
+		});
+		buffer.extend_from_slice(&[0; 3]);
+		buffer.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+		buffer.extend_from_slice(&metadata);
+
+		let layers = self.layers();
+		buffer.extend_from_slice(&(layers.len() as u32).to_le_bytes());
+
+		for neurons in layers {
+			let weights_amount = neurons.first().map(|n| n.weights.len()).unwrap_or_default();
+			buffer.extend_from_slice(&(neurons.len() as u32).to_le_bytes());
+			buffer.extend_from_slice(&(weights_amount as u32).to_le_bytes());
+
+			for neuron in neurons {
+				if neuron.weights.len() != weights_amount {
+					return Err(ModelError::InvalidBinary(
+						"all neurons in a layer must have the same amount of weights",
+					));
+				}
+				write_floats(&mut buffer, &neuron.weights, precision);
+			}
+
+			let biases: Vec<f32> = neurons.iter().map(|neuron| neuron.bias).collect();
+			write_floats(&mut buffer, &biases, precision);
+		}
+
+		Ok(buffer)
+	}
+
+	/// Decode a model in the compact binary format, see [`ModelFile::to_binary`].
+	pub fn from_binary(mut bytes: &[u8]) -> Result<ModelFile, ModelError> {
+		let mut magic = [0; 4];
+		bytes.read_exact(&mut magic)?;
+		if &magic != BINARY_MAGIC {
+			return Err(ModelError::InvalidBinary("missing magic bytes"));
+		}
+
+		let version = read_u32(&mut bytes)?;
+		if version > FORMAT_VERSION {
+			return Err(ModelError::UnsupportedVersion {
+				found: version,
+				supported: FORMAT_VERSION,
+			});
+		}
+
+		let mut flags = [0; 4];
+		bytes.read_exact(&mut flags)?;
+		let precision = match flags[0] {
+			0 => Precision::Single,
+			1 => Precision::Half,
+			_ => return Err(ModelError::InvalidBinary("unknown precision")),
+		};
+
+		let metadata_len = read_u32(&mut bytes)? as usize;
+		if metadata_len > bytes.len() {
+			return Err(ModelError::InvalidBinary("metadata is truncated"));
+		}
+		let (metadata, mut bytes) = bytes.split_at(metadata_len);
+		let metadata: ModelMetadata = serde_json::from_slice(metadata)?;
+
+		let layer_count = read_u32(&mut bytes)?;
+		if layer_count == 0 {
+			return Err(ModelError::InvalidBinary("model has no output layer"));
+		}
+
+		let mut layers = Vec::new();
+		for _ in 0..layer_count {
+			let neurons_amount = read_u32(&mut bytes)? as usize;
+			let weights_amount = read_u32(&mut bytes)? as usize;
+
+			// Check the layer fits in the rest of the file before allocating anything,
+			// the amounts come straight from the file and can't be trusted.
+			let layer_size = weights_amount
+				.checked_add(1)
+				.and_then(|values| values.checked_mul(neurons_amount))
+				.and_then(|values| values.checked_mul(precision.size()));
+			if layer_size.is_none_or(|layer_size| layer_size > bytes.len()) {
+				return Err(ModelError::InvalidBinary("layer is truncated"));
+			}
+
+			let mut neurons = Vec::with_capacity(neurons_amount);
+
+			for _ in 0..neurons_amount {
+				neurons.push(Neuron {
+					activation: 0.,
+					weights: read_floats(&mut bytes, weights_amount, precision)?,
+					bias: 0.,
+				});
+			}
+
+			let biases = read_floats(&mut bytes, neurons_amount, precision)?;
+			for (neuron, bias) in neurons.iter_mut().zip(biases) {
+				neuron.bias = bias;
+			}
+
+			layers.push(neurons);
+		}
+
+		let output = layers.pop().unwrap_or_default();
+		let activation_layers = layers
+			.into_iter()
+			.map(|neurons| ActivationLayer {
+				neurons,
+				..ActivationLayer::default()
+			})
+			.collect();
+
+		Ok(ModelFile::new(
+			metadata,
+			activation_layers,
+			OutputLayer { neurons: output },
+		))
+	}
+
+	/// Encode the model in `format`.
+	pub fn encode(&self, format: ModelFormat) -> Result<Vec<u8>, ModelError> {
+		let encoded = match format.encoding {
+			Encoding::Json => self.to_json()?.into_bytes(),
+			Encoding::Binary(precision) => self.to_binary(precision)?,
+		};
+
+		if !format.compressed {
+			return Ok(encoded);
+		}
+
+		let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+		encoder.write_all(&encoded)?;
+		Ok(encoder.finish()?)
+	}
+
+	/// Decode a model of any format.
+	/// The format is detected from the content, so the file extension doesn't matter.
+	pub fn decode(bytes: &[u8]) -> Result<ModelFile, ModelError> {
+		if bytes.starts_with(GZIP_MAGIC) {
+			let mut decompressed = Vec::new();
+			GzDecoder::new(bytes).read_to_end(&mut decompressed)?;
+			return ModelFile::decode(&decompressed);
+		}
+
+		if bytes.starts_with(BINARY_MAGIC) {
+			return ModelFile::from_binary(bytes);
+		}
+
+		let json = std::str::from_utf8(bytes)
+			.map_err(|_| ModelError::InvalidBinary("file is neither JSON nor a binary model"))?;
+		ModelFile::from_json(json)
+	}
+
+	/// Read a model file of any format.
+	pub fn read(path: impl AsRef<Path>) -> Result<ModelFile, ModelError> {
+		ModelFile::decode(&fs::read(path)?)
+	}
+
+	/// Write the model in the format matching the extension of `path`.
+	/// Missing folders are created and the file is replaced atomically.
+	pub fn write(&self, path: impl AsRef<Path>) -> Result<(), ModelError> {
+		let path = path.as_ref();
+		write_atomic(path, &self.encode(ModelFormat::from_path(path))?)?;
+		Ok(())
+	}
+}
+
+impl ModelFormat {
+	/// Pick the format from the file extension.
+	///
+	/// - `.bin` is the binary format with f32 values, `.f16.bin` uses f16 values.
+	/// - A trailing `.gz` compresses the file with gzip, e.g. `.bin.gz` or `.json.gz`.
+	/// - Everything else is JSON.
+	pub fn from_path(path: impl AsRef<Path>) -> ModelFormat {
+		let name = path
+			.as_ref()
+			.file_name()
+			.map(|name| name.to_string_lossy().to_lowercase())
+			.unwrap_or_default();
+
+		let (name, compressed) = match name.strip_suffix(".gz") {
+			Some(name) => (name, true),
+			None => (name.as_str(), false),
+		};
+
+		let encoding = match name.strip_suffix(".bin") {
+			Some(stem) if stem.ends_with(".f16") => Encoding::Binary(Precision::Half),
+			Some(_) => Encoding::Binary(Precision::Single),
+			None => Encoding::Json,
+		};
+
+		ModelFormat {
+			encoding,
+			compressed,
+		}
+	}
+}
+
+/// The folder bare model file names are resolved in.
+pub const NETWORKS_DIR: &str = "networks";
+
+/// Whether `path` is a plain file name without any directory.
+fn is_bare_name(path: &Path) -> bool {
+	path.parent()
+		.is_none_or(|parent| parent.as_os_str().is_empty())
+		&& !path.has_root()
+}
+
+/// The path a model should be loaded from.
+/// Paths that exist are used as they are, bare file names that don't exist
+/// fall back to the `networks` folder.
+pub fn load_path(filename: impl AsRef<Path>) -> PathBuf {
+	let path = filename.as_ref();
+
+	if is_bare_name(path) && !path.exists() {
+		return Path::new(NETWORKS_DIR).join(path);
+	}
+
+	path.to_path_buf()
+}
+
+/// The path a model should be saved to.
+/// Bare file names are saved in the `networks` folder, everything else is used as is.
+pub fn save_path(filename: impl AsRef<Path>) -> PathBuf {
+	let path = filename.as_ref();
+
+	if is_bare_name(path) {
+		return Path::new(NETWORKS_DIR).join(path);
+	}
+
+	path.to_path_buf()
+}
+
+impl LegacyModelFile {
+	/// Convert the legacy format to the current one.
+	/// Everything the legacy format did not store is derived from the layers,
+	/// or left unknown.
+	fn migrate(self) -> ModelFile {
+		let mut model = ModelFile::new(
+			ModelMetadata::default(),
+			self.activation_layers,
+			self.output_layer,
+		);
+		let shape = model.shape();
+
+		model.metadata = ModelMetadata {
+			input_size: shape[0],
+			shape,
+			activation: Activation::Sigmoid,
+			learning_rate: self.learning_rate,
+			epochs: None,
+			dataset: "mnist".to_string(),
+			training_accuracy: None,
+			accuracy: None,
+			dropout: Vec::new(),
+			config: None,
+		};
+
+		model
+	}
+}
+
+/// Check that `dropout` is empty or has a rate in `[0, 1)` for each of the `layers`.
+pub fn check_dropout(dropout: &[f32], layers: usize) -> Result<(), String> {
+	if !dropout.is_empty() && dropout.len() != layers {
+		return Err(format!(
+			"got {} dropout rates for {} activation layers",
+			dropout.len(),
+			layers
+		));
+	}
+
+	match dropout.iter().find(|rate| !(0. ..1.).contains(*rate)) {
+		Some(rate) => Err(format!("dropout rate {} is not in [0, 1)", rate)),
+		None => Ok(()),
+	}
+}
+
+/// Human readable name of the layer at `index`, counting from the first activation layer.
+fn layer_name(index: usize, layer_count: usize) -> String {
+	if index + 1 == layer_count {
+		"output layer".to_string()
+	} else {
+		format!("activation layer {}", index + 1)
+	}
+}
+
+fn read_u32(bytes: &mut &[u8]) -> Result<u32, ModelError> {
+	let mut buffer = [0; 4];
+	bytes.read_exact(&mut buffer)?;
+	Ok(u32::from_le_bytes(buffer))
+}
+
+fn write_floats(buffer: &mut Vec<u8>, values: &[f32], precision: Precision) {
+	for value in values {
+		match precision {
+			Precision::Single => buffer.extend_from_slice(&value.to_le_bytes()),
+			Precision::Half => buffer.extend_from_slice(&f16::from_f32(*value).to_le_bytes()),
+		}
+	}
+}
+
+fn read_floats(
+	bytes: &mut &[u8],
+	amount: usize,
+	precision: Precision,
+) -> Result<Vec<f32>, ModelError> {
+	let size = precision.size();
+	let length = amount
+		.checked_mul(size)
+		.filter(|length| *length <= bytes.len())
+		.ok_or(ModelError::InvalidBinary("tensor is truncated"))?;
+	let (tensor, rest) = bytes.split_at(length);
+	*bytes = rest;
+
+	let values = tensor
+		.chunks_exact(size)
+		.map(|chunk| match precision {
+			Precision::Single => f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]),
+			Precision::Half => f16::from_le_bytes([chunk[0], chunk[1]]).to_f32(),
+		})
+		.collect();
+
+	Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn neuron(weights: &[f32], bias: f32) -> Neuron {
+		Neuron {
+			activation: 0.,
+			weights: weights.to_vec(),
+			bias,
+		}
+	}
+
+	/// A model with the shape `[2, 3, 1]`, all values are exact in f16.
+	fn model() -> ModelFile {
+		let hidden = ActivationLayer {
+			neurons: vec![
+				neuron(&[0.5, -1.25], 0.25),
+				neuron(&[2., 0.], -0.5),
+				neuron(&[-0.75, 1.5], 1.),
+			],
+			..ActivationLayer::default()
+		};
+		let output = OutputLayer {
+			neurons: vec![neuron(&[1., -2., 0.125], 0.)],
+		};
+		let metadata = ModelMetadata {
+			input_size: 2,
+			shape: vec![2, 3, 1],
+			learning_rate: 0.1,
+			dataset: "mnist".to_string(),
+			..ModelMetadata::default()
+		};
+
+		ModelFile::new(metadata, vec![hidden], output)
+	}
+
+	fn assert_same_layers(left: &ModelFile, right: &ModelFile) {
+		let parameters = |model: &ModelFile| -> Vec<(Vec<f32>, f32)> {
+			model
+				.layers()
+				.iter()
+				.flat_map(|neurons| neurons.iter())
+				.map(|neuron| (neuron.weights.clone(), neuron.bias))
+				.collect()
+		};
+
+		assert_eq!(left.shape(), right.shape());
+		assert_eq!(parameters(left), parameters(right));
+		assert_eq!(left.metadata, right.metadata);
+	}
+
+	#[test]
+	fn json_round_trip() {
+		let model = model();
+		let json = model.to_json().unwrap();
+		assert!(json.contains(&format!("\"version\":{}", FORMAT_VERSION)));
+
+		let decoded = ModelFile::from_json(&json).unwrap();
+		assert_eq!(decoded.version, FORMAT_VERSION);
+		assert_same_layers(&decoded, &model);
+	}
+
+	#[test]
+	fn migrates_legacy_json() {
+		let legacy = r#"{
+			"learning_rate": 0.05,
+			"activation_layers": [{ "neurons": [
+				{ "activation": 0, "weights": [0.5, -1.25], "bias": 0.25 },
+				{ "activation": 0, "weights": [2, 0], "bias": -0.5 },
+				{ "activation": 0, "weights": [-0.75, 1.5], "bias": 1 }
+			] }],
+			"output_layer": { "neurons": [
+				{ "activation": 0, "weights": [1, -2, 0.125], "bias": 0 }
+			] }
+		}"#;
+
+		let model = ModelFile::from_json(legacy).unwrap();
+		assert_eq!(model.version, FORMAT_VERSION);
+		assert_eq!(model.metadata.shape, [2, 3, 1]);
+		assert_eq!(model.metadata.input_size, 2);
+		assert_eq!(model.metadata.learning_rate, 0.05);
+		assert_eq!(model.metadata.epochs, None);
+		assert_same_layers(
+			&model,
+			&ModelFile {
+				metadata: model.metadata.clone(),
+				..self::model()
+			},
+		);
+	}
+
+	#[test]
+	fn rejects_future_versions() {
+		let mut json: Value = serde_json::from_str(&model().to_json().unwrap()).unwrap();
+		json["version"] = (FORMAT_VERSION + 1).into();
+
+		assert!(matches!(
+			ModelFile::from_json(&json.to_string()),
+			Err(ModelError::UnsupportedVersion { found, supported })
+				if found == FORMAT_VERSION + 1 && supported == FORMAT_VERSION
+		));
+
+		json["version"] = "1".into();
+		assert!(matches!(
+			ModelFile::from_json(&json.to_string()),
+			Err(ModelError::InvalidVersion(_))
+		));
+	}
+
+	#[test]
+	fn validates_shape_and_values() {
+		let model = model();
+		model.validate(&[2, 3, 1]).unwrap();
+
+		assert!(matches!(
+			model.validate(&[2, 3, 3, 1]),
+			Err(ModelError::LayerCount {
+				expected: 4,
+				found: 3
+			})
+		));
+		assert!(matches!(
+			model.validate(&[2, 4, 1]),
+			Err(ModelError::NeuronCount {
+				expected: 4,
+				found: 3,
+				..
+			})
+		));
+		assert!(matches!(
+			model.validate(&[3, 3, 1]),
+			Err(ModelError::WeightCount {
+				neuron: 0,
+				expected: 3,
+				found: 2,
+				..
+			})
+		));
+
+		let mut broken = model.clone();
+		broken.output_layer.neurons[0].weights[1] = f32::NAN;
+		assert!(matches!(
+			broken.validate(&[2, 3, 1]),
+			Err(ModelError::NonFinite {
+				neuron: 0,
+				parameter: "weight",
+				..
+			})
+		));
+
+		let mut broken = model;
+		broken.metadata.dropout = vec![0.5, 0.5];
+		assert!(matches!(
+			broken.validate(&[2, 3, 1]),
+			Err(ModelError::InvalidDropout(_))
+		));
+	}
+
+	#[test]
+	fn binary_round_trip() {
+		let model = model();
+
+		for precision in [Precision::Single, Precision::Half] {
+			let bytes = model.to_binary(precision).unwrap();
+			let decoded = ModelFile::from_binary(&bytes).unwrap();
+			assert_same_layers(&decoded, &model);
+		}
+	}
+
+	#[test]
+	fn truncated_binary() {
+		let bytes = model().to_binary(Precision::Single).unwrap();
+
+		for length in 0..bytes.len() {
+			assert!(
+				ModelFile::from_binary(&bytes[..length]).is_err(),
+				"decoded a model truncated to {} bytes",
+				length
+			);
+		}
+	}
+
+	#[test]
+	fn oversized_binary_header() {
+		let metadata = serde_json::to_vec(&ModelMetadata::default()).unwrap();
+
+		for (neurons, weights) in [(u32::MAX, 0), (u32::MAX, u32::MAX), (1, u32::MAX)] {
+			let mut bytes = Vec::new();
+			bytes.extend_from_slice(BINARY_MAGIC);
+			bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+			bytes.extend_from_slice(&[0; 4]);
+			bytes.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+			bytes.extend_from_slice(&metadata);
+			bytes.extend_from_slice(&1u32.to_le_bytes());
+			bytes.extend_from_slice(&neurons.to_le_bytes());
+			bytes.extend_from_slice(&weights.to_le_bytes());
+
+			assert!(matches!(
+				ModelFile::from_binary(&bytes),
+				Err(ModelError::InvalidBinary(_))
+			));
+		}
+	}
+
+	#[test]
+	fn bare_names_fall_back_to_the_networks_folder() {
+		assert_eq!(
+			load_path("missing-model.json"),
+			Path::new(NETWORKS_DIR).join("missing-model.json")
+		);
+		// Files that exist are loaded from where they are.
+		assert_eq!(load_path("Cargo.toml"), Path::new("Cargo.toml"));
+		assert_eq!(
+			load_path("models/missing.json"),
+			Path::new("models/missing.json")
+		);
+
+		assert_eq!(
+			save_path("model.json"),
+			Path::new(NETWORKS_DIR).join("model.json")
+		);
+		assert_eq!(
+			save_path("Cargo.toml"),
+			Path::new(NETWORKS_DIR).join("Cargo.toml")
+		);
+		assert_eq!(save_path("./model.json"), Path::new("./model.json"));
+		assert_eq!(save_path("/tmp/model.json"), Path::new("/tmp/model.json"));
+	}
+}
@@ -1,321 +1,775 @@
 use std::{
-	fs::File,
-	io::{Read, Write},
+	path::Path,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	time::Instant,
 };
 
 use log::info;
-use mnist::{Mnist, MnistBuilder};
-use ndarray::{Array2, Array3};
-use serde::{Deserialize, Serialize};
+use ndarray::Axis;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 use crate::{
+	callback::{BatchMetrics, Callback, Control},
+	checkpoint::{Checkpoint, CheckpointOptions, EpochMetrics, TrainingState},
+	config::{ExperimentConfig, Regularization},
+	dataset::Dataset,
+	error::{Error, Result},
+	gradient::Gradients,
 	layers::{ActivationLayer, InputLayer, OutputLayer},
-	utils::{activation_functions::sigmoid, save_neuron_as_image},
+	model::{check_dropout, load_path, save_path, ModelFile, ModelMetadata},
+	neuron::Neuron,
+	utils::{activation_functions::Activation, loss_functions::Loss, save_neuron_as_image},
 };
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+/// The predictions a network made for every image in a dataset.
+#[derive(Clone, Debug, Default)]
+pub struct Evaluation {
+	pub labels: Vec<u8>,
+	pub predictions: Vec<Option<usize>>,
+	pub costs: Vec<f32>,
+}
+
+#[derive(Clone, Debug)]
 pub struct Network {
-	// Skip serializing fields that are not needed for the network to work.
-	#[serde(skip_serializing, skip_deserializing)]
-	pub training_images: Box<Array3<f32>>,
-	#[serde(skip_serializing, skip_deserializing)]
-	pub training_labels: Box<Array2<u8>>,
-	#[serde(skip_serializing, skip_deserializing)]
-	pub test_images: Box<Array3<f32>>,
-	#[serde(skip_serializing, skip_deserializing)]
-	pub test_labels: Box<Array2<u8>>,
+	/// The dataset used for training and testing, loaded when it's first needed.
+	pub dataset: Option<Arc<Dataset>>,
 
 	pub learning_rate: f32,
+	/// The activation function of the activation layers.
+	pub activation: Activation,
+	pub loss: Loss,
+	/// Penalties and constraints on the weights while training.
+	pub regularization: Regularization,
+	/// The experiment config the network was created with, if any.
+	pub config: Option<ExperimentConfig>,
+
+	// Training history that is stored in the model metadata.
+	pub epochs: Option<usize>,
+	pub training_accuracy: Option<f32>,
+	pub accuracy: Option<f32>,
 
 	// All the layers of the network.
-	#[serde(skip_serializing, skip_deserializing)]
 	pub input_layer: InputLayer,
-	pub activation_layers: (ActivationLayer, ActivationLayer),
+	pub activation_layers: Vec<ActivationLayer>,
 	pub output_layer: OutputLayer,
 }
 
+impl Evaluation {
+	pub fn with_capacity(capacity: usize) -> Evaluation {
+		Evaluation {
+			labels: Vec::with_capacity(capacity),
+			predictions: Vec::with_capacity(capacity),
+			costs: Vec::with_capacity(capacity),
+		}
+	}
+
+	pub fn push(&mut self, label: u8, prediction: Option<usize>, cost: f32) {
+		self.labels.push(label);
+		self.predictions.push(prediction);
+		self.costs.push(cost);
+	}
+
+	pub fn len(&self) -> usize {
+		self.labels.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.labels.is_empty()
+	}
+
+	/// Whether the image at `index` was classified correctly.
+	pub fn is_correct(&self, index: usize) -> bool {
+		self.predictions[index] == Some(self.labels[index] as usize)
+	}
+
+	/// The amount of correctly classified images.
+	pub fn correct(&self) -> usize {
+		(0..self.len()).filter(|i| self.is_correct(*i)).count()
+	}
+
+	/// The accuracy in percent.
+	pub fn accuracy(&self) -> f32 {
+		(self.correct() as f32 / self.len() as f32) * 100.
+	}
+
+	/// The average cost of the entire dataset.
+	pub fn avg_cost(&self) -> f32 {
+		self.costs.iter().sum::<f32>() / self.costs.len() as f32
+	}
+}
+
 impl Network {
-	pub fn new(learning_rate: f32, shape: (usize, usize, usize, usize)) -> Network {
-		// Setup the dataset and allocate it on the heap.
-		let Mnist {
-			trn_img,
-			trn_lbl,
-			tst_img,
-			tst_lbl,
-			..
-		} = MnistBuilder::new()
-			.label_format_digit()
-			.training_set_length(50_000)
-			.test_set_length(10_000)
-			.finalize();
-
-		let training_images = Box::new(
-			Array3::from_shape_vec((50_000, 28, 28), trn_img)
-				.expect("Error converting images to Array3 struct")
-				.map(|x| *x as f32 / 256.0),
-		);
-		let training_labels = Box::new(
-			Array2::from_shape_vec((50_000, 1), trn_lbl)
-				.expect("Error converting training labels to Array2 struct")
-				.map(|x| *x as u8),
-		);
+	/// Create a network with random weights.
+	/// The shape is the amount of neurons in every layer, starting with the input
+	/// layer and ending with the output layer, e.g. `[784, 16, 16, 10]`.
+	pub fn new(learning_rate: f32, shape: &[usize]) -> Result<Network> {
+		Network::with_rng(learning_rate, shape, &mut rand::thread_rng())
+	}
 
-		let test_images = Box::new(
-			Array3::from_shape_vec((10_000, 28, 28), tst_img)
-				.expect("Error converting images to Array3 struct")
-				.map(|x| *x as f32 / 256.),
-		);
-		let test_labels = Box::new(
-			Array2::from_shape_vec((10_000, 1), tst_lbl)
-				.expect("Error converting testing labels to Array2 struct")
-				.map(|x| *x as u8),
-		);
+	/// Create a network with random weights taken from `rng`.
+	pub fn with_rng(learning_rate: f32, shape: &[usize], rng: &mut impl Rng) -> Result<Network> {
+		check_shape(shape)?;
 
-		// Return a new network.
+		let activation_layers = shape
+			.windows(2)
+			.take(shape.len() - 2)
+			.map(|sizes| ActivationLayer::with_rng(sizes[1], sizes[0], rng))
+			.collect();
+		let output_layer =
+			OutputLayer::with_rng(shape[shape.len() - 1], shape[shape.len() - 2], rng);
+
+		Ok(Network::from_layers(
+			learning_rate,
+			shape[0],
+			activation_layers,
+			output_layer,
+		))
+	}
+
+	/// Create a network from its layers, which need to fit together.
+	fn from_layers(
+		learning_rate: f32,
+		input_size: usize,
+		activation_layers: Vec<ActivationLayer>,
+		output_layer: OutputLayer,
+	) -> Network {
 		Network {
-			training_images,
-			training_labels,
-			test_images,
-			test_labels,
+			dataset: None,
 			learning_rate,
-			input_layer: InputLayer::new(shape.0),
-			activation_layers: (
-				ActivationLayer::new(shape.1, shape.0),
-				ActivationLayer::new(shape.2, shape.1),
-			),
-			output_layer: OutputLayer::new(shape.3, shape.2),
+			activation: Activation::default(),
+			loss: Loss::default(),
+			regularization: Regularization::default(),
+			config: None,
+			epochs: Some(0),
+			training_accuracy: None,
+			accuracy: None,
+			input_layer: InputLayer::new(input_size),
+			activation_layers,
+			output_layer,
 		}
 	}
 
-	pub fn train(&mut self, iterations: usize) {
-		for iteration in 0..iterations {
-			// An array to store the cost of each iteration.
-			let mut cost_array = Vec::with_capacity(self.training_images.len());
-			// Stores how many images the network has gotten correct.
-			let mut correct_images = 0;
+	/// Create a network as described by an experiment config.
+	/// The initial weights are derived from the seed of the config, if it has one.
+	pub fn from_config(
+		config: &ExperimentConfig,
+		input_size: usize,
+		output_size: usize,
+	) -> Result<Network> {
+		let mut shape = vec![input_size];
+		shape.extend_from_slice(&config.hidden);
+		shape.push(output_size);
+
+		let mut network = match config.seed {
+			Some(seed) => {
+				// Use a different stream than the training state, which has the same seed.
+				let mut rng = ChaCha8Rng::seed_from_u64(seed);
+				rng.set_stream(1);
+				Network::with_rng(config.learning_rate(), &shape, &mut rng)?
+			}
+			None => Network::new(config.learning_rate(), &shape)?,
+		};
+
+		network.activation = config.activation;
+		network.loss = config.loss;
+		network.regularization = config.regularization;
+		network.set_dropout(&config.dropout_rates(config.hidden.len())?)?;
+		network.config = Some(config.clone());
+		Ok(network)
+	}
+
+	/// Create a network from a saved model, using the shape stored in the model.
+	/// The layers are checked against the shape before they are used, as both come from the file.
+	pub fn from_model(model: ModelFile) -> Result<Network> {
+		check_shape(&model.metadata.shape)?;
+		model.validate(&model.metadata.shape)?;
 
-			let images = self.training_images.clone();
-			let labels = self.training_labels.clone();
-			let outer_images = images.outer_iter();
-			let outer_labels = labels.outer_iter();
+		let ModelFile {
+			metadata,
+			activation_layers,
+			output_layer,
+			..
+		} = model;
+		let mut network = Network::from_layers(
+			metadata.learning_rate,
+			metadata.input_size,
+			activation_layers,
+			output_layer,
+		);
+		network.activation = metadata.activation;
+		if let Some(config) = &metadata.config {
+			network.loss = config.loss;
+			network.regularization = config.regularization;
+		}
+		network.set_dropout(&metadata.dropout)?;
+
+		// Keep the training history and the config of the loaded model.
+		network.config = metadata.config;
+		network.epochs = metadata.epochs;
+		network.training_accuracy = metadata.training_accuracy;
+		network.accuracy = metadata.accuracy;
+
+		Ok(network)
+	}
+
+	/// Load a network from a JSON or binary file, using the shape stored in the file.
+	/// Bare file names that don't exist are looked up in the `networks` folder.
+	pub fn from_file(filename: impl Into<String>) -> Result<Network> {
+		let name_into: String = filename.into();
+		let network = Network::from_model(ModelFile::read(load_path(&name_into))?)?;
+
+		info!("Loaded network from file {}", name_into);
+		Ok(network)
+	}
+
+	/// Restore a network from a checkpoint,
+	/// returning it together with the training state to continue training from.
+	pub fn resume(checkpoint: Checkpoint) -> Result<(Network, TrainingState)> {
+		Ok((Network::from_model(checkpoint.model)?, checkpoint.state))
+	}
+
+	pub fn train(&mut self, iterations: usize) -> Result<()> {
+		let mut state = TrainingState::new(iterations, false, rand::random());
+		self.train_from(&mut state, None, &mut [], &AtomicBool::new(false))?;
+		Ok(())
+	}
+
+	/// Train the network, continuing from `state`, calling the hooks of every callback.
+	/// Stops early once `stop` is set, writing a checkpoint if `checkpoints` is given.
+	/// Returns whether every dataset iteration has completed, or a callback stopped training.
+	pub fn train_from(
+		&mut self,
+		state: &mut TrainingState,
+		checkpoints: Option<&CheckpointOptions>,
+		callbacks: &mut [Box<dyn Callback>],
+		stop: &AtomicBool,
+	) -> Result<bool> {
+		let dataset = self.dataset()?;
+		let images = &dataset.training_images;
+		let labels = &dataset.training_labels;
+
+		while !state.is_finished() {
+			let iteration = state.iteration;
+			let started = Instant::now();
+
+			// Remember the learning rate training started with, the schedule is based on it.
+			let base_learning_rate = *state.base_learning_rate.get_or_insert(self.learning_rate);
+			self.learning_rate = state.schedule.learning_rate(base_learning_rate, iteration);
+
+			// Start a new iteration, unless one was interrupted halfway.
+			if state.sample == 0 {
+				state.order = (0..labels.nrows()).collect();
+				if state.shuffle {
+					state.order.shuffle(&mut state.rng);
+				}
+				// Stores how many images the network has gotten correct.
+				state.correct = 0;
+				// Stores the summed cost of the iteration.
+				state.cost_sum = 0.;
+				state.gradient_norm_sum = 0.;
+				state.seconds = 0.;
+			}
+
+			for callback in callbacks.iter_mut() {
+				callback.on_epoch_start(self, iteration)?;
+			}
 
 			// Loop that runs over all training images.
-			for (image, image_label) in outer_images.zip(outer_labels) {
+			while let Some(&index) = state.order.get(state.sample) {
+				if stop.load(Ordering::Relaxed) {
+					state.seconds += started.elapsed().as_secs_f32();
+					if let Some(options) = checkpoints {
+						self.write_checkpoint(state, &options.path)?;
+					}
+					return Ok(false);
+				}
+
+				let image = images.index_axis(Axis(0), index);
+				let image_label = labels[[index, 0]];
+
 				// Make the image pixels into a 1D array for the input layer.
-				let image_buffer = image.into_shape((1, 784)).unwrap();
-				let raw_image = image_buffer.iter().map(|x| *x).collect();
+				let raw_image = state.augmentation.apply(image, &mut state.rng);
 
-				// Feed the current image forward through the network.
-				self.feed_forward(raw_image);
+				// Feed the current image forward through the network,
+				// dropping neurons only while training.
+				if iteration != 0 {
+					self.feed_forward_dropout(raw_image, &mut state.rng);
+				} else {
+					self.feed_forward(raw_image);
+				}
 
 				// Get the neuron with the highest activation.
 				let neuron_opt = self.get_most_active_neuron();
-				if let Some(neuron) = neuron_opt {
-					if neuron.0 == image_label[0] as usize {
-						correct_images += 1;
-					}
+				let correct = neuron_opt.is_some_and(|neuron| neuron.0 == image_label as usize);
+				if correct {
+					state.correct += 1;
 				}
 
 				// Get the cost after an images has been fed forward.
-				cost_array.push(self.calculate_iteration_cost(image_label[0]));
+				let cost = self.calculate_iteration_cost(image_label);
+				state.cost_sum += cost;
 
 				// Don't back propagate on the first iteration.
 				if iteration != 0 {
 					// Back propagate the error.
-					self.backpropagate(image_label[0]);
+					state.gradient_norm_sum += self.backpropagate(image_label);
 				}
+
+				let batch = BatchMetrics {
+					iteration,
+					sample: state.sample,
+					label: image_label,
+					correct,
+					cost,
+				};
+				for callback in callbacks.iter_mut() {
+					callback.on_batch_end(self, &batch)?;
+				}
+
+				state.sample += 1;
 			}
 
+			state.seconds += started.elapsed().as_secs_f32();
+
 			// Calculate the accuracy of the network.
-			let accuracy = ((correct_images as f32) / labels.len() as f32) * 100.;
+			let accuracy = ((state.correct as f32) / state.order.len() as f32) * 100.;
 			// Calculate the average cost of the entire dataset.
-			let avg_cost = cost_array.iter().sum::<f32>() / cost_array.len() as f32;
+			let avg_cost = state.cost_sum / state.order.len() as f32;
+			// Only iterations that back propagated have trained the network.
+			if iteration != 0 {
+				self.epochs = self.epochs.map(|epochs| epochs + 1);
+				self.training_accuracy = Some(accuracy);
+				// The test accuracy no longer describes the trained network.
+				self.accuracy = None;
+			}
+
 			info!(
 				"Dataset iteration {} complete – Accuracy: {}% ({}), avg. cost: {}",
-				iteration, accuracy, correct_images, avg_cost
+				iteration, accuracy, state.correct, avg_cost
 			);
+
+			let mut epoch = EpochMetrics {
+				iteration,
+				accuracy,
+				correct: state.correct,
+				avg_cost,
+				validation_accuracy: None,
+				validation_cost: None,
+				regularization_cost: self.regularization_cost(),
+				learning_rate: self.learning_rate,
+				seconds: state.seconds,
+				gradient_norm: state.gradient_norm_sum / state.order.len() as f32,
+			};
+			if callbacks.iter().any(|callback| callback.evaluates()) {
+				let evaluation = self.evaluate()?;
+				epoch.validation_accuracy = Some(evaluation.accuracy());
+				epoch.validation_cost = Some(evaluation.avg_cost());
+				for callback in callbacks.iter_mut() {
+					callback.on_evaluate(self, &evaluation)?;
+				}
+			}
+
+			let mut control = Control::Continue;
+			for callback in callbacks.iter_mut() {
+				if callback.on_epoch_end(self, &epoch)? == Control::Stop {
+					control = Control::Stop;
+				}
+			}
+
+			state.history.push(epoch);
+			state.iteration += 1;
+			state.sample = 0;
+			if control == Control::Stop {
+				info!(target: "console", "Training stopped after dataset iteration {}", iteration);
+				state.iterations = state.iteration;
+			}
+
+			if let Some(options) = checkpoints {
+				let every = options.every;
+				if every.is_some_and(|every| state.iteration.is_multiple_of(every)) {
+					self.write_checkpoint(state, &options.path)?;
+				}
+			}
 		}
+
+		Ok(true)
+	}
+
+	/// Write the network and the training state to a checkpoint.
+	pub fn write_checkpoint(&self, state: &TrainingState, path: impl AsRef<Path>) -> Result<()> {
+		let path = path.as_ref();
+		Checkpoint::new(self.to_model(), state.clone()).write(path)?;
+
+		info!(
+			target: "console",
+			"Wrote checkpoint at dataset iteration {} (image {}) to {}",
+			state.iteration,
+			state.sample,
+			path.display()
+		);
+		Ok(())
+	}
+
+	pub fn test(&mut self) -> Result<()> {
+		self.test_with(|_| {})
 	}
 
-	pub fn test(&mut self) {
-		let images = self.test_images.clone();
-		let labels = self.test_labels.clone();
+	/// Test the network, calling `on_image` with the evaluation so far after every image.
+	pub fn test_with(&mut self, on_image: impl FnMut(&Evaluation)) -> Result<()> {
+		let evaluation = self.evaluate_with(on_image)?;
+		self.accuracy = Some(evaluation.accuracy());
+
+		info!(
+			target: "console",
+			"Test complete – Accuracy: {}% ({}), avg. cost: {}",
+			evaluation.accuracy(),
+			evaluation.correct(),
+			evaluation.avg_cost()
+		);
+		Ok(())
+	}
+
+	/// Run the whole test dataset through the network and record every prediction.
+	pub fn evaluate(&mut self) -> Result<Evaluation> {
+		self.evaluate_with(|_| {})
+	}
+
+	/// Evaluate the network, calling `on_image` with the evaluation so far after every image.
+	pub fn evaluate_with(&mut self, mut on_image: impl FnMut(&Evaluation)) -> Result<Evaluation> {
+		let dataset = self.dataset()?;
+		let images = &dataset.test_images;
+		let labels = &dataset.test_labels;
 		let outer_images = images.outer_iter();
 		let outer_labels = labels.outer_iter();
 
-		// An array to store the cost of each iteration.
-		let mut cost_array = Vec::with_capacity(self.test_images.len());
-		// Stores how many images the network has gotten correct.
-		let mut correct_images = 0;
+		let mut evaluation = Evaluation::with_capacity(labels.len());
 
 		for (image, image_label) in outer_images.zip(outer_labels) {
 			// Make the image pixels into a 1D array for the input layer.
-			let image_buffer = image.into_shape((1, 784)).unwrap();
-			let raw_image = image_buffer.iter().map(|x| *x).collect();
+			let raw_image = image.iter().copied().collect();
 
 			// Feed the current image forward through the network.
 			self.feed_forward(raw_image);
 
 			// Get the neuron with the highest activation.
-			let neuron = self.get_most_active_neuron();
-			if let Some(neuron) = neuron {
-				if neuron.0 == image_label[0] as usize {
-					correct_images += 1;
-				}
-			}
-
+			let prediction = self.get_most_active_neuron().map(|neuron| neuron.0);
 			// Get the cost after an images has been fed forward.
-			cost_array.push(self.calculate_iteration_cost(image_label[0]));
+			let cost = self.calculate_iteration_cost(image_label[0]);
+
+			evaluation.push(image_label[0], prediction, cost);
+			on_image(&evaluation);
 		}
 
-		// Calculate the accuracy of the network.
-		let accuracy = ((correct_images as f32) / labels.len() as f32) * 100.;
-		// Calculate the average cost of the entire dataset.
-		let avg_cost = cost_array.iter().sum::<f32>() / cost_array.len() as f32;
-		info!(
-			target: "console",
-			"Test complete – Accuracy: {}% ({}), avg. cost: {}",
-			accuracy, correct_images, avg_cost
-		);
+		Ok(evaluation)
 	}
 
-	/// Feed an raw image through the network, and update all the neuron activations.
-	pub fn feed_forward(&mut self, raw_image: Vec<f32>) {
-		self.input_layer.activations = raw_image;
+	/// The dataset of the network, loading the dataset of its config if no dataset has been set.
+	/// Networks without a config use MNIST.
+	pub fn dataset(&mut self) -> Result<Arc<Dataset>> {
+		let dataset = match &self.dataset {
+			Some(dataset) => dataset.clone(),
+			None => {
+				let kind = self.config.as_ref().map(|config| config.dataset);
+				let dataset = Arc::new(Dataset::load(kind.unwrap_or_default())?);
+				self.dataset = Some(dataset.clone());
+				dataset
+			}
+		};
+
+		let input_size = self.shape()[0];
+		if dataset.input_size() != input_size {
+			return Err(Error::Shape(format!(
+				"the network has {} inputs, but the dataset has {}",
+				input_size,
+				dataset.input_size()
+			)));
+		}
 
-		// From input layer to the first activation layer.
-		for neuron in self.activation_layers.0.neurons.iter_mut() {
-			let mut sum: f32 = 0.;
-			let weights = neuron.weights.iter();
-			let activations = self.input_layer.activations.iter();
+		Ok(dataset)
+	}
 
-			for (weight, activation) in weights.zip(activations) {
-				sum += weight * activation;
-			}
+	/// Feed an input through the network without changing it, returning the output activations.
+	pub fn infer(&self, input: &[f32]) -> Vec<f32> {
+		self.infer_batch(&[input.to_vec()])
+			.pop()
+			.expect("One input gives one output")
+	}
 
-			neuron.activation = sigmoid(sum + (neuron.bias));
+	/// Feed a batch of inputs through the network in one pass,
+	/// returning the output activations for every input.
+	pub fn infer_batch(&self, inputs: &[Vec<f32>]) -> Vec<Vec<f32>> {
+		let mut activations = inputs.to_vec();
+		for layer in self.activation_layers.iter() {
+			activations = outputs(&layer.neurons, &activations, self.activation);
 		}
 
-		// From activation layer 1 to activation layer 2.
-		for current_neuron in self.activation_layers.1.neurons.iter_mut() {
-			let mut sum: f32 = 0.;
-			let wieghts = current_neuron.weights.iter();
-			let previous_neurons = self.activation_layers.0.neurons.iter();
+		outputs(
+			&self.output_layer.neurons,
+			&activations,
+			Activation::Sigmoid,
+		)
+	}
 
-			for (weight, neuron) in wieghts.zip(previous_neurons) {
-				sum += weight * neuron.activation;
-			}
+	/// Feed an raw image through the network, and update all the neuron activations.
+	pub fn feed_forward(&mut self, raw_image: Vec<f32>) {
+		self.input_layer.activations = raw_image;
 
-			current_neuron.activation = sigmoid(sum + (current_neuron.bias));
+		// From the input layer through every activation layer.
+		let mut previous_activations: Option<Vec<f32>> = None;
+		for layer in self.activation_layers.iter_mut() {
+			let inputs = previous_activations
+				.as_deref()
+				.unwrap_or(&self.input_layer.activations);
+			activate(&mut layer.neurons, inputs, self.activation);
+			layer.mask.clear();
+			previous_activations = Some(layer.activations());
 		}
 
-		// From activation layer 2 to output layer.
-		for output_neuron in self.output_layer.neurons.iter_mut() {
-			let mut sum: f32 = 0.;
-			let weights = output_neuron.weights.iter();
-			let previous_neurons = self.activation_layers.1.neurons.iter();
+		// From the last activation layer to the output layer.
+		let inputs = previous_activations
+			.as_deref()
+			.unwrap_or(&self.input_layer.activations);
+		activate(&mut self.output_layer.neurons, inputs, Activation::Sigmoid);
+	}
 
-			for (weight, neuron) in weights.zip(previous_neurons) {
-				sum += weight * neuron.activation;
-			}
+	/// Feed an raw image through the network like [`Network::feed_forward`], dropping the
+	/// neurons of every activation layer with its dropout rate.
+	/// The dropout masks are kept for [`Network::backpropagate`].
+	pub fn feed_forward_dropout(&mut self, raw_image: Vec<f32>, rng: &mut impl Rng) {
+		self.input_layer.activations = raw_image;
 
-			output_neuron.activation = sigmoid(sum + (output_neuron.bias));
+		let mut previous_activations: Option<Vec<f32>> = None;
+		for layer in self.activation_layers.iter_mut() {
+			let inputs = previous_activations
+				.as_deref()
+				.unwrap_or(&self.input_layer.activations);
+			activate(&mut layer.neurons, inputs, self.activation);
+			layer.sample_mask(rng);
+			previous_activations = Some(layer.masked_activations());
 		}
+
+		let inputs = previous_activations
+			.as_deref()
+			.unwrap_or(&self.input_layer.activations);
+		activate(&mut self.output_layer.neurons, inputs, Activation::Sigmoid);
 	}
 
 	/// Back propagate the error through the network.
-	/// Label is the correct label for the current image.
-	pub fn backpropagate(&mut self, label: u8) {
-		// Calulate the desired output for the network.
-		let mut desire = Vec::with_capacity(self.output_layer.neurons.len());
-		for i in 0..self.output_layer.neurons.len() {
-			if i == label as usize {
-				desire.push(1.);
-			} else {
-				desire.push(0.);
-			}
+	/// Label is the correct label for the image that was fed forward last.
+	/// Neurons dropped while feeding forward don't contribute to the gradients.
+	/// Returns the norm of the gradients.
+	pub fn backpropagate(&mut self, label: u8) -> f32 {
+		let mut activations = vec![self.input_layer.activations.clone()];
+		activations.extend(
+			self.activation_layers
+				.iter()
+				.map(|layer| layer.activations()),
+		);
+		activations.push(self.output_layer.activations());
+		let masks: Vec<Vec<f32>> = self
+			.activation_layers
+			.iter()
+			.map(|layer| layer.mask.clone())
+			.collect();
+
+		let gradients = self.gradients_with_masks(&activations, &masks, label);
+		self.apply_gradients(&gradients);
+		gradients.norm()
+	}
+
+	/// Take a single gradient descent step on the average gradients of a mini-batch.
+	/// Returns the average cost of the batch before the step.
+	pub fn train_batch(&mut self, inputs: &[Vec<f32>], labels: &[u8]) -> f32 {
+		if inputs.is_empty() {
+			return 0.;
 		}
 
-		// Calculate the error signal for the output layer.
-		let mut output_error_signal = Vec::with_capacity(self.output_layer.neurons.len());
-		for (i, neuron) in self.output_layer.neurons.iter().enumerate() {
-			// The label is the desired output for the network.
-			output_error_signal.push(desire[i] - neuron.activation);
+		let mut gradients = Gradients::zeros(&self.layers());
+		let mut cost = 0.;
+		for (input, label) in inputs.iter().zip(labels) {
+			let activations = self.forward(input);
+			let outputs = activations.last().expect("There is always an output");
+			cost += outputs
+				.iter()
+				.enumerate()
+				.map(|(i, activation)| self.loss.cost(*activation, desired(i, *label)))
+				.sum::<f32>();
+			gradients.add(&self.gradients_from(&activations, *label));
 		}
 
-		// Update the weights and bias for the output layer.
-		for (i, output_neuron) in self.output_layer.neurons.iter_mut().enumerate() {
-			for j in 0..self.activation_layers.1.neurons.len() {
-				let previous_activation = output_neuron.activation;
-				let delta_weight =
-					self.learning_rate * output_error_signal[i] * previous_activation;
+		let scale = 1. / inputs.len() as f32;
+		gradients.scale(scale);
+		self.apply_gradients(&gradients);
+		cost * scale
+	}
 
-				output_neuron.weights[j] += delta_weight;
-			}
+	/// The gradients of the cost of `input` with respect to every weight and bias.
+	pub fn gradients(&self, input: &[f32], label: u8) -> Gradients {
+		self.gradients_from(&self.forward(input), label)
+	}
 
-			let delta_bias = self.learning_rate * output_error_signal[i];
-			output_neuron.bias += delta_bias;
-		}
+	/// The gradients of the cost, given the activations of every layer
+	/// starting with the input layer, as returned by [`Network::forward`].
+	pub fn gradients_from(&self, activations: &[Vec<f32>], label: u8) -> Gradients {
+		self.gradients_with_masks(activations, &[], label)
+	}
 
-		// Calculate the error signal for the second activation layer
-		let mut second_activation_error_signal =
-			Vec::with_capacity(self.activation_layers.1.neurons.len());
-		for (i, second_activation_neuron) in self.activation_layers.1.neurons.iter().enumerate() {
-			let mut error_sum = 0.0;
-			for j in 0..self.output_layer.neurons.len() {
-				error_sum += output_error_signal[j] * self.output_layer.neurons[j].weights[i];
+	/// The gradients of the cost like [`Network::gradients_from`], with the dropout mask
+	/// of every activation layer applied to its activations. Empty masks drop nothing.
+	pub fn gradients_with_masks(
+		&self,
+		activations: &[Vec<f32>],
+		masks: &[Vec<f32>],
+		label: u8,
+	) -> Gradients {
+		let layers = self.layers();
+		let mut gradients = Gradients::zeros(&layers);
+
+		// The gradient of the cost with respect to the weighted input of every output neuron.
+		let outputs = &activations[layers.len()];
+		let mut deltas: Vec<f32> = outputs
+			.iter()
+			.enumerate()
+			.map(|(i, activation)| -self.loss.error_signal(*activation, desired(i, label)))
+			.collect();
+
+		// Walk the layers backwards, starting with the output layer.
+		for index in (0..layers.len()).rev() {
+			let previous_activations = &activations[index];
+			// The mask of the previous layer, the input layer never has one.
+			let mask = index
+				.checked_sub(1)
+				.and_then(|previous| masks.get(previous))
+				.filter(|mask| !mask.is_empty());
+			let scale = |j: usize| mask.map_or(1., |mask| mask[j]);
+
+			for (i, delta) in deltas.iter().enumerate() {
+				for (j, previous_activation) in previous_activations.iter().enumerate() {
+					gradients.weights[index][i][j] = delta * previous_activation * scale(j);
+				}
+				gradients.biases[index][i] = *delta;
 			}
 
-			second_activation_error_signal.push(
-				error_sum
-					* second_activation_neuron.activation
-					* (1.0 - second_activation_neuron.activation),
-			);
+			if index == 0 {
+				break;
+			}
+
+			// Propagate the deltas to the previous layer, using the weights before any update.
+			deltas = previous_activations
+				.iter()
+				.enumerate()
+				.map(|(j, activation)| {
+					let error_sum: f32 = deltas
+						.iter()
+						.zip(layers[index])
+						.map(|(delta, neuron)| delta * neuron.weights[j])
+						.sum();
+					error_sum * self.activation.derivative(*activation) * scale(j)
+				})
+				.collect();
 		}
 
-		// Update the weights and bias for the second activation layer
-		for (i, activation_neuron) in self.activation_layers.1.neurons.iter_mut().enumerate() {
-			for j in 0..self.activation_layers.0.neurons.len() {
-				let previoues_activation = self.activation_layers.0.neurons[j].activation;
-				let delta_weight =
-					self.learning_rate * second_activation_error_signal[i] * previoues_activation;
+		gradients
+	}
 
-				activation_neuron.weights[j] += delta_weight;
+	/// Take a gradient descent step of the size of the learning rate,
+	/// regularizing the weights as configured.
+	pub fn apply_gradients(&mut self, gradients: &Gradients) {
+		let learning_rate = self.learning_rate;
+		let regularization = self.regularization;
+		let layers = self
+			.activation_layers
+			.iter_mut()
+			.map(|layer| &mut layer.neurons)
+			.chain(std::iter::once(&mut self.output_layer.neurons));
+
+		for ((neurons, weights), biases) in layers.zip(&gradients.weights).zip(&gradients.biases) {
+			for ((neuron, weights), bias) in neurons.iter_mut().zip(weights).zip(biases) {
+				regularization.step(&mut neuron.weights, weights, learning_rate);
+				neuron.bias -= learning_rate * bias;
 			}
+		}
+	}
 
-			let delta_bias = self.learning_rate * second_activation_error_signal[i];
-			activation_neuron.bias += delta_bias;
+	/// The activations of every layer for `input`, starting with the input itself.
+	pub fn forward(&self, input: &[f32]) -> Vec<Vec<f32>> {
+		let mut activations = vec![input.to_vec()];
+		for layer in self.activation_layers.iter() {
+			let inputs = activations.last().expect("There is always an input");
+			let outputs = layer
+				.neurons
+				.iter()
+				.map(|neuron| output(neuron, inputs, self.activation))
+				.collect();
+			activations.push(outputs);
 		}
 
-		// Calculate the error signal for the first activation layer
-		let mut first_activation_error_signal =
-			Vec::with_capacity(self.activation_layers.0.neurons.len());
-		for (i, first_activation_neuron) in self.activation_layers.0.neurons.iter().enumerate() {
-			let mut error_sum = 0.0;
-			for j in 0..self.activation_layers.1.neurons.len() {
-				error_sum += second_activation_error_signal[j]
-					* self.activation_layers.1.neurons[j].weights[i];
-			}
+		let inputs = activations.last().expect("There is always an input");
+		let outputs = self
+			.output_layer
+			.neurons
+			.iter()
+			.map(|neuron| output(neuron, inputs, Activation::Sigmoid))
+			.collect();
+		activations.push(outputs);
+		activations
+	}
 
-			first_activation_error_signal.push(
-				error_sum
-					* first_activation_neuron.activation
-					* (1.0 - first_activation_neuron.activation),
-			);
-		}
+	/// The cost of `input` without changing the network.
+	pub fn cost(&self, input: &[f32], label: u8) -> f32 {
+		self.infer(input)
+			.iter()
+			.enumerate()
+			.map(|(i, activation)| self.loss.cost(*activation, desired(i, label)))
+			.sum()
+	}
 
-		// Update the weights and bias for the second activation layer
-		for (i, activation_neuron) in self.activation_layers.0.neurons.iter_mut().enumerate() {
-			for (j, previous_activation) in self.input_layer.activations.iter().enumerate() {
-				let delta_weight =
-					self.learning_rate * first_activation_error_signal[i] * previous_activation;
+	/// The neurons of every activation layer and the output layer.
+	pub fn layers(&self) -> Vec<&[Neuron]> {
+		self.activation_layers
+			.iter()
+			.map(|layer| layer.neurons.as_slice())
+			.chain(std::iter::once(self.output_layer.neurons.as_slice()))
+			.collect()
+	}
 
-				activation_neuron.weights[j] += delta_weight;
-			}
+	/// The penalty of the weights that is added to the cost while training,
+	/// on top of the cost of the images.
+	pub fn regularization_cost(&self) -> f32 {
+		self.layers()
+			.iter()
+			.flat_map(|neurons| neurons.iter())
+			.map(|neuron| self.regularization.penalty(&neuron.weights))
+			.sum()
+	}
+
+	/// The dropout rate of every activation layer, or empty if no layer uses dropout.
+	pub fn dropout(&self) -> Vec<f32> {
+		let dropout: Vec<f32> = self
+			.activation_layers
+			.iter()
+			.map(|layer| layer.dropout)
+			.collect();
+
+		if dropout.iter().all(|rate| *rate == 0.) {
+			return Vec::new();
+		}
+		dropout
+	}
 
-			let delta_bias = self.learning_rate * first_activation_error_signal[i];
-			activation_neuron.bias += delta_bias;
+	/// Set the dropout rate of every activation layer, empty disables dropout.
+	pub fn set_dropout(&mut self, dropout: &[f32]) -> Result<()> {
+		check_dropout(dropout, self.activation_layers.len()).map_err(Error::Shape)?;
+
+		for (index, layer) in self.activation_layers.iter_mut().enumerate() {
+			layer.dropout = dropout.get(index).copied().unwrap_or_default();
+			layer.mask.clear();
 		}
+		Ok(())
 	}
 
 	/// Calculate the cost of the current image iteration.
@@ -324,11 +778,7 @@ impl Network {
 		let mut cost: f32 = 0.;
 
 		for (index, neuron) in self.output_layer.neurons.iter().enumerate() {
-			if index as u8 == label {
-				cost += f32::powf(2., neuron.activation - 1.);
-			} else {
-				cost += f32::powf(2., neuron.activation - 0.);
-			}
+			cost += self.loss.cost(neuron.activation, desired(index, label));
 		}
 
 		cost
@@ -359,54 +809,227 @@ impl Network {
 		most_active_output_neuron
 	}
 
-	/// Save the network state to a json file.
-	pub fn save_layers(&mut self, filename: impl Into<String>) -> anyhow::Result<()> {
-		let name_into = filename.into();
+	/// The amount of neurons in every layer, including the input layer.
+	pub fn shape(&self) -> Vec<usize> {
+		let input_size = self
+			.activation_layers
+			.first()
+			.map(|layer| &layer.neurons)
+			.unwrap_or(&self.output_layer.neurons)
+			.first()
+			.map(|neuron| neuron.weights.len())
+			.unwrap_or_default();
+
+		let mut shape = vec![input_size];
+		shape.extend(
+			self.activation_layers
+				.iter()
+				.map(|layer| layer.neurons.len()),
+		);
+		shape.push(self.output_layer.neurons.len());
+		shape
+	}
+
+	/// Describe the network for the metadata of a saved model.
+	pub fn metadata(&self) -> ModelMetadata {
+		let shape = self.shape();
+
+		ModelMetadata {
+			input_size: shape[0],
+			shape,
+			activation: self.activation,
+			learning_rate: self.learning_rate,
+			epochs: self.epochs,
+			dataset: self
+				.config
+				.as_ref()
+				.map_or("mnist", |config| config.dataset.name())
+				.to_string(),
+			training_accuracy: self.training_accuracy,
+			accuracy: self.accuracy,
+			dropout: self.dropout(),
+			config: self.config.clone(),
+		}
+	}
 
-		for neuron in self.output_layer.neurons.iter_mut() {
+	/// The network as it is stored on disk.
+	pub fn to_model(&self) -> ModelFile {
+		let mut output_layer = self.output_layer.clone();
+		for neuron in output_layer.neurons.iter_mut() {
 			neuron.activation = 0.;
 		}
 
-		// Save the network to the "networks" folder.
-		let mut file = File::create(&format!("networks/{}", name_into))?;
-		let json = serde_json::to_string(&self)?;
-		file.write_all(json.as_bytes())?;
+		ModelFile::new(
+			self.metadata(),
+			self.activation_layers.clone(),
+			output_layer,
+		)
+	}
+
+	/// Save the network state to a file.
+	/// The format is picked from the file extension,
+	/// see [`ModelFormat::from_path`](crate::model::ModelFormat::from_path).
+	pub fn save_layers(&self, filename: impl Into<String>) -> Result<()> {
+		let name_into = filename.into();
+
+		// Bare file names are saved to the "networks" folder.
+		self.to_model().write(save_path(&name_into))?;
 
 		info!("Wrote network to file {}", name_into);
 		Ok(())
 	}
 
-	/// Load the network state from a json file.
-	/// Will automatically look in the `networks` folder.
-	pub fn load_layers(&mut self, filename: impl Into<String>) -> anyhow::Result<()> {
+	/// Load the network state from a JSON or binary file.
+	/// Bare file names that don't exist are looked up in the `networks` folder.
+	/// Files written by older versions are migrated to the current format.
+	/// Fails if the layers in the file don't match the shape of this network,
+	/// use [`Network::from_file`] to load a network of any shape.
+	pub fn load_layers(&mut self, filename: impl Into<String>) -> Result<()> {
 		let name_into: String = filename.into();
-		// Load the file from the "networks" folder.
-		let mut file = File::open(&format!("networks/{}", name_into))?;
-		let mut contents = String::new();
-		file.read_to_string(&mut contents)?;
-		let loaded: Network = serde_json::from_str(&contents)?;
+		// Bare file names fall back to the "networks" folder.
+		self.load_model(ModelFile::read(load_path(&name_into))?)?;
+
+		info!("Loaded network from file {}", name_into);
+		Ok(())
+	}
+
+	/// Replace the layers of the network with the layers of `model`.
+	/// Fails if the layers don't match the shape of this network.
+	pub fn load_model(&mut self, model: ModelFile) -> Result<()> {
+		model.validate(&self.shape())?;
 
 		// Set the networks layers to the loaded layers.
-		self.activation_layers = loaded.activation_layers;
-		self.output_layer = loaded.output_layer;
+		self.activation_layers = model.activation_layers;
+		self.output_layer = model.output_layer;
+		self.set_dropout(&model.metadata.dropout)?;
+
+		// Keep the training history and the config of the loaded model.
+		self.config = model.metadata.config;
+		self.epochs = model.metadata.epochs;
+		self.training_accuracy = model.metadata.training_accuracy;
+		self.accuracy = model.metadata.accuracy;
 
-		info!("Loaded network from file {}", name_into);
 		Ok(())
 	}
 
 	/// Generate images of the neurons in the network.
-	/// Will automatically look in the `networks` folder.
-	pub fn generate_images(&self) {
+	/// Will automatically save them in the `images` folder.
+	pub fn generate_images(&self) -> Result<()> {
 		for (i, neuron) in self.output_layer.neurons.iter().enumerate() {
-			save_neuron_as_image(neuron, &format!("output_neuron_{}.png", i));
+			save_neuron_as_image(neuron, format!("output_neuron_{}.png", i))?;
 		}
 
-		for (i, neuron) in self.activation_layers.1.neurons.iter().enumerate() {
-			save_neuron_as_image(neuron, &format!("activation_2_neuron_{}.png", i));
+		for (layer_index, layer) in self.activation_layers.iter().enumerate() {
+			for (i, neuron) in layer.neurons.iter().enumerate() {
+				save_neuron_as_image(
+					neuron,
+					format!("activation_{}_neuron_{}.png", layer_index + 1, i),
+				)?;
+			}
 		}
 
-		for (i, neuron) in self.activation_layers.0.neurons.iter().enumerate() {
-			save_neuron_as_image(neuron, &format!("activation_1_neuron_{}.png", i));
-		}
+		Ok(())
+	}
+}
+
+/// Check that `shape` describes a network with an input and an output layer,
+/// and at least one neuron in every layer.
+fn check_shape(shape: &[usize]) -> Result<()> {
+	if shape.len() < 2 {
+		return Err(Error::Shape(
+			"a network needs at least an input and an output layer".to_string(),
+		));
+	}
+	if shape.contains(&0) {
+		return Err(Error::Shape(format!(
+			"every layer needs at least one neuron, got {:?}",
+			shape
+		)));
+	}
+
+	Ok(())
+}
+
+/// The desired activation of output neuron `index` for an image of `label`.
+fn desired(index: usize, label: u8) -> f32 {
+	if index == label as usize {
+		1.
+	} else {
+		0.
+	}
+}
+
+/// Update the activation of every neuron from the activations of the previous layer.
+fn activate(neurons: &mut [Neuron], inputs: &[f32], activation: Activation) {
+	for neuron in neurons.iter_mut() {
+		neuron.activation = output(neuron, inputs, activation);
+	}
+}
+
+/// The activations of a layer for every set of activations of the previous layer.
+fn outputs(neurons: &[Neuron], batch: &[Vec<f32>], activation: Activation) -> Vec<Vec<f32>> {
+	batch
+		.iter()
+		.map(|inputs| {
+			neurons
+				.iter()
+				.map(|neuron| output(neuron, inputs, activation))
+				.collect()
+		})
+		.collect()
+}
+
+/// The activation of a neuron for the activations of the previous layer.
+fn output(neuron: &Neuron, inputs: &[f32], activation: Activation) -> f32 {
+	let mut sum: f32 = 0.;
+	let weights = neuron.weights.iter();
+
+	for (weight, activation) in weights.zip(inputs) {
+		sum += weight * activation;
+	}
+
+	activation.apply(sum + (neuron.bias))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::model::ModelError;
+
+	#[test]
+	fn loads_a_model_with_its_layers() {
+		let network = Network::new(0.1, &[3, 4, 2]).unwrap();
+		let loaded = Network::from_model(network.to_model()).unwrap();
+
+		assert_eq!(loaded.shape(), [3, 4, 2]);
+		let input = [0.5, 0.25, 1.];
+		assert_eq!(loaded.infer(&input), network.infer(&input));
+	}
+
+	#[test]
+	fn rejects_models_that_dont_match_their_shape() {
+		let model = Network::new(0.1, &[3, 4, 2]).unwrap().to_model();
+
+		// A huge shape is rejected before any weights are created for it.
+		let mut huge = model.clone();
+		huge.metadata.shape = vec![3, usize::MAX / 2, 2];
+		assert!(matches!(
+			Network::from_model(huge),
+			Err(Error::Model(ModelError::NeuronCount { .. }))
+		));
+
+		let mut input_size = model.clone();
+		input_size.metadata.input_size = 5;
+		assert!(matches!(
+			Network::from_model(input_size),
+			Err(Error::Model(ModelError::InputSize {
+				expected: 3,
+				found: 5
+			}))
+		));
+
+		let mut empty = model;
+		empty.metadata.shape = vec![3];
+		assert!(matches!(Network::from_model(empty), Err(Error::Shape(_))));
 	}
 }
@@ -1,3 +1,4 @@
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -9,16 +10,21 @@ pub struct Neuron {
 
 impl Neuron {
 	pub fn new(weights_amount: usize) -> Neuron {
+		Neuron::with_rng(weights_amount, &mut rand::thread_rng())
+	}
+
+	/// Create a neuron with random weights and bias taken from `rng`.
+	pub fn with_rng(weights_amount: usize, rng: &mut impl Rng) -> Neuron {
 		let mut weights_buf = Vec::with_capacity(weights_amount);
 
 		for _ in 0..weights_amount {
-			weights_buf.push(rand::random::<f32>() * 2. - 1.);
+			weights_buf.push(rng.gen::<f32>() * 2. - 1.);
 		}
 
 		Neuron {
 			activation: 0.,
 			weights: weights_buf,
-			bias: rand::random(),
+			bias: rng.gen(),
 		}
 	}
 }
@@ -0,0 +1,168 @@
+use image::{Rgb, RgbImage};
+
+use crate::checkpoint::EpochMetrics;
+
+pub const PLOT_WIDTH: u32 = 960;
+pub const PLOT_HEIGHT: u32 = 720;
+/// The space around and between the panels.
+const MARGIN: u32 = 40;
+/// The length of the dashes of validation curves, in pixels.
+const DASH: f32 = 6.;
+
+/// The colors of the runs, in order. Runs after the last color start over.
+pub const COLORS: [(&str, Rgb<u8>); 6] = [
+	("blue", Rgb([31, 119, 180])),
+	("orange", Rgb([255, 127, 14])),
+	("green", Rgb([44, 160, 44])),
+	("red", Rgb([214, 39, 40])),
+	("purple", Rgb([148, 103, 189])),
+	("brown", Rgb([140, 86, 75])),
+];
+const BACKGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+const AXIS: Rgb<u8> = Rgb([0, 0, 0]);
+const GRID: Rgb<u8> = Rgb([225, 225, 225]);
+
+/// Training curves of one or more runs.
+pub struct Plot {
+	/// The cost on top and the accuracy below, with training as solid
+	/// and validation as dashed lines. Grid lines divide both axes in quarters.
+	pub image: RgbImage,
+	/// The cost at the top of the cost panel, which starts at 0.
+	/// The accuracy panel always goes from 0% to 100%.
+	pub max_cost: f32,
+	/// The first and last dataset iteration on the horizontal axis.
+	pub iterations: (usize, usize),
+}
+
+/// The value of a curve at an iteration, if it has one.
+type Curve = fn(&EpochMetrics) -> Option<f32>;
+
+/// A panel of the plot, mapping iterations and values to pixels.
+struct Panel {
+	left: f32,
+	top: f32,
+	width: f32,
+	height: f32,
+	iterations: (usize, usize),
+	max: f32,
+}
+
+/// Plot the cost and accuracy of every run.
+pub fn plot_curves(runs: &[Vec<EpochMetrics>]) -> Plot {
+	let mut image = RgbImage::from_pixel(PLOT_WIDTH, PLOT_HEIGHT, BACKGROUND);
+
+	let all = || runs.iter().flatten();
+	let first = all().map(|metrics| metrics.iteration).min().unwrap_or(0);
+	let last = all().map(|metrics| metrics.iteration).max().unwrap_or(0);
+	let iterations = (first, last.max(first + 1));
+	let max_cost = all()
+		.flat_map(|metrics| [Some(metrics.avg_cost), metrics.validation_cost])
+		.flatten()
+		.filter(|cost| cost.is_finite())
+		.fold(0., f32::max);
+	let max_cost = if max_cost > 0. { max_cost } else { 1. };
+
+	let panel_height = (PLOT_HEIGHT - 3 * MARGIN) as f32 / 2.;
+	let panel = |index: u32, max: f32| Panel {
+		left: MARGIN as f32,
+		top: (MARGIN + index * (MARGIN + panel_height as u32)) as f32,
+		width: (PLOT_WIDTH - 2 * MARGIN) as f32,
+		height: panel_height,
+		iterations,
+		max,
+	};
+	let cost_panel = panel(0, max_cost);
+	let accuracy_panel = panel(1, 100.);
+
+	for panel in [&cost_panel, &accuracy_panel] {
+		panel.draw_axes(&mut image);
+	}
+
+	for (run, (_, color)) in runs.iter().zip(COLORS.iter().cycle()) {
+		let curves: [(&Panel, Curve, bool); 4] = [
+			(&cost_panel, |metrics| Some(metrics.avg_cost), false),
+			(&cost_panel, |metrics| metrics.validation_cost, true),
+			(&accuracy_panel, |metrics| Some(metrics.accuracy), false),
+			(&accuracy_panel, |metrics| metrics.validation_accuracy, true),
+		];
+
+		for (panel, value, dashed) in curves {
+			let points: Vec<(f32, f32)> = run
+				.iter()
+				.filter_map(|metrics| Some(panel.point(metrics.iteration, value(metrics)?)))
+				.collect();
+
+			if let [point] = points.as_slice() {
+				draw_line(&mut image, *point, *point, *color, None);
+			}
+			// The dashes continue from one line to the next.
+			let mut travelled = 0.;
+			for line in points.windows(2) {
+				let dash = dashed.then_some(travelled);
+				travelled += draw_line(&mut image, line[0], line[1], *color, dash);
+			}
+		}
+	}
+
+	Plot {
+		image,
+		max_cost,
+		iterations,
+	}
+}
+
+impl Panel {
+	/// The pixel of a value at an iteration.
+	fn point(&self, iteration: usize, value: f32) -> (f32, f32) {
+		let (first, last) = self.iterations;
+		let x = (iteration - first) as f32 / (last - first) as f32;
+		let y = (value / self.max).clamp(0., 1.);
+		(
+			self.left + x * self.width,
+			self.top + (1. - y) * self.height,
+		)
+	}
+
+	fn draw_axes(&self, image: &mut RgbImage) {
+		let (left, right) = (self.left, self.left + self.width);
+		let (top, bottom) = (self.top, self.top + self.height);
+
+		for quarter in 1..=4 {
+			let y = bottom - self.height * quarter as f32 / 4.;
+			draw_line(image, (left, y), (right, y), GRID, None);
+		}
+		draw_line(image, (left, top), (left, bottom), AXIS, None);
+		draw_line(image, (left, bottom), (right, bottom), AXIS, None);
+	}
+}
+
+/// Draw a line two pixels wide, returning its length.
+/// With `dash`, every other dash is left out, starting `dash` pixels into the pattern.
+fn draw_line(
+	image: &mut RgbImage,
+	from: (f32, f32),
+	to: (f32, f32),
+	color: Rgb<u8>,
+	dash: Option<f32>,
+) -> f32 {
+	let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+	let length = (dx * dx + dy * dy).sqrt();
+	let steps = (length * 2.).ceil().max(1.) as usize;
+
+	for step in 0..=steps {
+		let t = step as f32 / steps as f32;
+		if dash.is_some_and(|offset| ((offset + t * length) / DASH) as usize % 2 == 1) {
+			continue;
+		}
+
+		let (x, y) = (from.0 + t * dx, from.1 + t * dy);
+		for (offset_x, offset_y) in [(0., 0.), (1., 0.), (0., 1.), (1., 1.)] {
+			let (x, y) = ((x + offset_x - 0.5) as i64, (y + offset_y - 0.5) as i64);
+			if (0..image.width() as i64).contains(&x) && (0..image.height() as i64).contains(&y) {
+				image.put_pixel(x as u32, y as u32, color);
+			}
+		}
+	}
+
+	length
+}
@@ -0,0 +1,223 @@
+use std::path::Path;
+
+use image::{DynamicImage, ImageResult, Luma};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	network::Network,
+	preprocessing::{GrayImage32, Preprocessing},
+};
+
+/// The width and height of the images the network is trained on.
+pub const IMAGE_SIZE: u32 = 28;
+
+/// The digit a network predicted for an input, with the probability of every digit.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Prediction {
+	pub digit: usize,
+	pub probabilities: Vec<f32>,
+}
+
+/// One of the most likely digits of a prediction.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Candidate {
+	pub digit: usize,
+	pub probability: f32,
+}
+
+/// A prediction with its three most likely digits, as sent by the server and the stdio protocol.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PredictionReport {
+	pub digit: usize,
+	pub probabilities: Vec<f32>,
+	pub top: Vec<Candidate>,
+}
+
+impl Prediction {
+	/// Turn the output activations of a network into a prediction.
+	/// The sigmoid outputs are normalised so the probabilities sum to 1.
+	pub fn from_outputs(outputs: &[f32]) -> Prediction {
+		let sum: f32 = outputs.iter().sum();
+		let probabilities: Vec<f32> = outputs
+			.iter()
+			.map(|output| if sum > 0. { output / sum } else { 0. })
+			.collect();
+
+		let digit = probabilities
+			.iter()
+			.enumerate()
+			.max_by(|a, b| a.1.total_cmp(b.1))
+			.map_or(0, |(digit, _)| digit);
+
+		Prediction {
+			digit,
+			probabilities,
+		}
+	}
+
+	/// The `count` most likely digits, the most likely first.
+	pub fn top(&self, count: usize) -> Vec<Candidate> {
+		let mut candidates: Vec<Candidate> = self
+			.probabilities
+			.iter()
+			.enumerate()
+			.map(|(digit, probability)| Candidate {
+				digit,
+				probability: *probability,
+			})
+			.collect();
+
+		candidates.sort_by(|a, b| b.probability.total_cmp(&a.probability));
+		candidates.truncate(count);
+		candidates
+	}
+}
+
+impl From<Prediction> for PredictionReport {
+	fn from(prediction: Prediction) -> PredictionReport {
+		PredictionReport {
+			digit: prediction.digit,
+			top: prediction.top(3),
+			probabilities: prediction.probabilities,
+		}
+	}
+}
+
+impl Network {
+	/// Predict the digit of an input without changing the network.
+	pub fn predict(&self, input: &[f32]) -> Prediction {
+		Prediction::from_outputs(&self.infer(input))
+	}
+}
+
+/// Load an image file as input for the network.
+pub fn load_image(path: impl AsRef<Path>, preprocessing: &Preprocessing) -> ImageResult<Vec<f32>> {
+	Ok(image_to_input(&image::open(path)?, preprocessing))
+}
+
+/// Convert an image of any size to 28×28 grayscale pixels between 0 and 1,
+/// with a light digit on a dark background like the MNIST images.
+/// Transparent pixels count as white, and dark digits on a light background are inverted.
+pub fn image_to_input(image: &DynamicImage, preprocessing: &Preprocessing) -> Vec<f32> {
+	let image = image.to_luma_alpha8();
+	let mut gray = GrayImage32::from_fn(image.width(), image.height(), |x, y| {
+		let [luma, alpha] = image.get_pixel(x, y).0;
+		let alpha = alpha as f32 / 255.;
+		Luma([(luma as f32 * alpha + 255. * (1. - alpha)) / 256.])
+	});
+
+	// The background is whatever most of the border is.
+	if border_mean(&gray) > 0.5 {
+		for pixel in gray.pixels_mut() {
+			pixel.0[0] = 1. - pixel.0[0];
+		}
+	}
+
+	preprocessing.apply(&gray)
+}
+
+/// The average value of the pixels on the edge of an image.
+fn border_mean(image: &GrayImage32) -> f32 {
+	let (width, height) = image.dimensions();
+	let border: Vec<f32> = image
+		.enumerate_pixels()
+		.filter(|(x, y, _)| *x == 0 || *y == 0 || *x == width - 1 || *y == height - 1)
+		.map(|(_, _, pixel)| pixel.0[0])
+		.collect();
+
+	border.iter().sum::<f32>() / border.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+	use image::{GrayImage, Rgba, RgbaImage};
+
+	use super::*;
+	use crate::preprocessing::{bounding_box, from_pixels, DIGIT_SIZE, INK_LEVEL};
+
+	/// A `size`×`size` image of a bar a quarter as wide and half as high as the image.
+	fn bar(size: u32, ink: u8, background: u8) -> GrayImage {
+		GrayImage::from_fn(size, size, |x, y| {
+			let inside =
+				(size * 3 / 8..size * 5 / 8).contains(&x) && (size / 4..size * 3 / 4).contains(&y);
+			Luma([if inside { ink } else { background }])
+		})
+	}
+
+	fn input(image: impl Into<DynamicImage>) -> Vec<f32> {
+		image_to_input(&image.into(), &Preprocessing::default())
+	}
+
+	fn assert_close(a: &[f32], b: &[f32]) {
+		assert_eq!(a.len(), b.len());
+		for (a, b) in a.iter().zip(b) {
+			assert!((a - b).abs() < 0.01, "{} vs. {}", a, b);
+		}
+	}
+
+	#[test]
+	fn dark_digits_are_inverted() {
+		let light_on_dark = input(bar(28, 255, 0));
+		let dark_on_light = input(bar(28, 0, 255));
+
+		assert_eq!(light_on_dark.len(), (IMAGE_SIZE * IMAGE_SIZE) as usize);
+		assert!(light_on_dark.iter().any(|pixel| *pixel > 0.9));
+		assert_close(&dark_on_light, &light_on_dark);
+		// The corners are background.
+		assert!(dark_on_light[0] < INK_LEVEL);
+		assert!(dark_on_light[dark_on_light.len() - 1] < INK_LEVEL);
+	}
+
+	#[test]
+	fn transparent_pixels_are_white() {
+		let bar = bar(28, 0, 255);
+		let transparent = RgbaImage::from_fn(28, 28, |x, y| {
+			if bar.get_pixel(x, y).0[0] == 0 {
+				Rgba([0, 0, 0, 255])
+			} else {
+				// Transparent black, which would be drawn on a white page.
+				Rgba([0, 0, 0, 0])
+			}
+		});
+
+		assert_close(&input(transparent), &input(bar));
+	}
+
+	#[test]
+	fn images_of_any_size_are_resized() {
+		for size in [14, 28, 280] {
+			let pixels = input(bar(size, 255, 0));
+			assert_eq!(pixels.len(), (IMAGE_SIZE * IMAGE_SIZE) as usize);
+
+			let image = from_pixels(pixels, IMAGE_SIZE, IMAGE_SIZE).unwrap();
+			let (_, _, width, height) = bounding_box(&image).unwrap();
+			assert_eq!(height, DIGIT_SIZE, "{}", size);
+			assert!(width < height, "{}", size);
+		}
+
+		let empty = input(GrayImage::new(100, 50));
+		assert_eq!(empty, vec![0.; (IMAGE_SIZE * IMAGE_SIZE) as usize]);
+	}
+
+	#[test]
+	fn top_candidates_are_sorted() {
+		let prediction = Prediction::from_outputs(&[0.1, 0.5, 0.2, 0.9, 0., 0., 0., 0.3, 0., 0.]);
+		assert_eq!(prediction.digit, 3);
+		assert!((prediction.probabilities.iter().sum::<f32>() - 1.).abs() < 1e-6);
+
+		let top = prediction.top(3);
+		let digits: Vec<usize> = top.iter().map(|candidate| candidate.digit).collect();
+		assert_eq!(digits, [3, 1, 7]);
+		assert!(top
+			.windows(2)
+			.all(|pair| pair[0].probability >= pair[1].probability));
+		assert_eq!(top[0].probability, prediction.probabilities[3]);
+
+		assert_eq!(prediction.top(20).len(), 10);
+		assert!(prediction.top(0).is_empty());
+
+		let report = PredictionReport::from(prediction.clone());
+		assert_eq!(report.top, top);
+		assert_eq!(report.digit, prediction.digit);
+	}
+}
@@ -0,0 +1,250 @@
+use image::{imageops::FilterType, ImageBuffer, Luma};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	error::{Error, Result},
+	predict::IMAGE_SIZE,
+};
+
+/// The digits in the MNIST images fit in a box of this size.
+pub const DIGIT_SIZE: u32 = 20;
+
+/// Pixels at or below this value count as background when looking for the digit,
+/// so noise and compression artifacts don't end up in the bounding box.
+pub const INK_LEVEL: f32 = 0.1;
+
+/// A grayscale image with pixels between 0 and 1.
+pub type GrayImage32 = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+/// How an image of a light digit on a dark background is made to look like an MNIST image.
+/// The digit is cropped, resized to fit in a 20×20 box keeping its aspect ratio,
+/// and centred by its centre of mass in a 28×28 image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preprocessing {
+	/// Pixels darker than this are set to 0 before anything else.
+	pub threshold: Option<f32>,
+	/// Straighten slanted digits using the image moments.
+	pub deskew: bool,
+}
+
+impl Preprocessing {
+	/// Preprocess an image, returning the 28×28 pixels as a 1D array.
+	pub fn apply(&self, image: &GrayImage32) -> Vec<f32> {
+		let mut image = image.clone();
+		if let Some(level) = self.threshold {
+			threshold(&mut image, level);
+		}
+
+		let Some((x, y, width, height)) = bounding_box(&image) else {
+			// Nothing is drawn, so there is nothing to center.
+			return vec![0.; (IMAGE_SIZE * IMAGE_SIZE) as usize];
+		};
+
+		let digit = image::imageops::crop_imm(&image, x, y, width, height).to_image();
+		let mut image = center_by_mass(&fit(&digit, DIGIT_SIZE), IMAGE_SIZE);
+		if self.deskew {
+			image = deskew(&image);
+		}
+
+		image.into_raw()
+	}
+}
+
+/// Create an image from its pixels, row by row.
+pub fn from_pixels(pixels: Vec<f32>, width: u32, height: u32) -> Result<GrayImage32> {
+	let len = pixels.len();
+	GrayImage32::from_raw(width, height, pixels).ok_or_else(|| {
+		Error::Shape(format!(
+			"{} pixels don't make a {}×{} image",
+			len, width, height
+		))
+	})
+}
+
+/// Set every pixel darker than `level` to 0.
+pub fn threshold(image: &mut GrayImage32, level: f32) {
+	for pixel in image.pixels_mut() {
+		if pixel.0[0] < level {
+			pixel.0[0] = 0.;
+		}
+	}
+}
+
+/// The smallest rectangle containing every pixel brighter than [`INK_LEVEL`],
+/// as `(x, y, width, height)`.
+pub fn bounding_box(image: &GrayImage32) -> Option<(u32, u32, u32, u32)> {
+	let lit = image
+		.enumerate_pixels()
+		.filter(|(_, _, pixel)| pixel.0[0] > INK_LEVEL)
+		.map(|(x, y, _)| (x, y));
+
+	let (mut min_x, mut min_y, mut max_x, mut max_y) = (u32::MAX, u32::MAX, 0, 0);
+	let mut any = false;
+	for (x, y) in lit {
+		any = true;
+		min_x = min_x.min(x);
+		min_y = min_y.min(y);
+		max_x = max_x.max(x);
+		max_y = max_y.max(y);
+	}
+
+	any.then(|| (min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+}
+
+/// Resize an image so its longest side is `size`, keeping the aspect ratio.
+pub fn fit(image: &GrayImage32, size: u32) -> GrayImage32 {
+	let (width, height) = image.dimensions();
+	let scale = size as f32 / width.max(height) as f32;
+	let new_width = ((width as f32 * scale).round() as u32).clamp(1, size);
+	let new_height = ((height as f32 * scale).round() as u32).clamp(1, size);
+
+	image::imageops::resize(image, new_width, new_height, FilterType::Triangle)
+}
+
+/// The centre of mass of the pixel values, as `(x, y)`.
+pub fn center_of_mass(image: &GrayImage32) -> Option<(f32, f32)> {
+	let (mut mass, mut x_sum, mut y_sum) = (0., 0., 0.);
+	for (x, y, pixel) in image.enumerate_pixels() {
+		mass += pixel.0[0];
+		x_sum += x as f32 * pixel.0[0];
+		y_sum += y as f32 * pixel.0[0];
+	}
+
+	(mass > 0.).then(|| (x_sum / mass, y_sum / mass))
+}
+
+/// Place an image in a `size`×`size` image, with its centre of mass in the middle.
+/// The image is moved less when it would otherwise not fit.
+pub fn center_by_mass(image: &GrayImage32, size: u32) -> GrayImage32 {
+	let (width, height) = image.dimensions();
+	let (center_x, center_y) =
+		center_of_mass(image).unwrap_or((width as f32 / 2., height as f32 / 2.));
+	let middle = (size as f32 - 1.) / 2.;
+
+	let offset_x = (middle - center_x).round().clamp(0., (size - width) as f32) as i64;
+	let offset_y = (middle - center_y)
+		.round()
+		.clamp(0., (size - height) as f32) as i64;
+
+	let mut centered = GrayImage32::new(size, size);
+	image::imageops::replace(&mut centered, image, offset_x, offset_y);
+	centered
+}
+
+/// Straighten a slanted digit by shearing it horizontally,
+/// so the second order moments no longer correlate x and y.
+pub fn deskew(image: &GrayImage32) -> GrayImage32 {
+	let Some((center_x, center_y)) = center_of_mass(image) else {
+		return image.clone();
+	};
+
+	let (mut covariance, mut variance_y) = (0., 0.);
+	for (x, y, pixel) in image.enumerate_pixels() {
+		let (dx, dy) = (x as f32 - center_x, y as f32 - center_y);
+		covariance += dx * dy * pixel.0[0];
+		variance_y += dy * dy * pixel.0[0];
+	}
+
+	if variance_y <= f32::EPSILON {
+		return image.clone();
+	}
+
+	let skew = covariance / variance_y;
+	let (width, height) = image.dimensions();
+	GrayImage32::from_fn(width, height, |x, y| {
+		let source_x = x as f32 + skew * (y as f32 - center_y);
+		Luma([sample_row(image, source_x, y)])
+	})
+}
+
+/// Linearly interpolate the pixel at a fractional x position in row `y`.
+fn sample_row(image: &GrayImage32, x: f32, y: u32) -> f32 {
+	let left = x.floor();
+	let fraction = x - left;
+	let pixel = |x: f32| {
+		if x < 0. || x >= image.width() as f32 {
+			0.
+		} else {
+			image.get_pixel(x as u32, y).0[0]
+		}
+	};
+
+	pixel(left) * (1. - fraction) + pixel(left + 1.) * fraction
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn image_with(size: u32, pixels: &[(u32, u32)]) -> GrayImage32 {
+		let mut image = GrayImage32::new(size, size);
+		for (x, y) in pixels {
+			image.put_pixel(*x, *y, Luma([1.]));
+		}
+		image
+	}
+
+	#[test]
+	fn empty_image() {
+		let image = GrayImage32::new(IMAGE_SIZE, IMAGE_SIZE);
+		assert_eq!(bounding_box(&image), None);
+		assert_eq!(center_of_mass(&image), None);
+		assert_eq!(deskew(&image), image);
+
+		let pixels = Preprocessing {
+			threshold: Some(0.5),
+			deskew: true,
+		}
+		.apply(&image);
+		assert_eq!(pixels, vec![0.; (IMAGE_SIZE * IMAGE_SIZE) as usize]);
+	}
+
+	#[test]
+	fn bounding_box_ignores_faint_pixels() {
+		let mut image = image_with(10, &[(2, 3), (7, 5)]);
+		image.put_pixel(9, 9, Luma([INK_LEVEL]));
+		assert_eq!(bounding_box(&image), Some((2, 3, 6, 3)));
+	}
+
+	#[test]
+	fn centers_a_single_pixel() {
+		let centered = center_by_mass(&image_with(1, &[(0, 0)]), IMAGE_SIZE);
+		assert_eq!(centered.dimensions(), (IMAGE_SIZE, IMAGE_SIZE));
+		assert_eq!(bounding_box(&centered), Some((14, 14, 1, 1)));
+	}
+
+	#[test]
+	fn fits_keeping_the_aspect_ratio() {
+		let fitted = |width, height| fit(&GrayImage32::new(width, height), DIGIT_SIZE).dimensions();
+		assert_eq!(fitted(10, 5), (20, 10));
+		assert_eq!(fitted(3, 12), (5, 20));
+		assert_eq!(fitted(40, 40), (20, 20));
+		assert_eq!(fitted(100, 1), (20, 1));
+	}
+
+	#[test]
+	fn deskews_a_slanted_stroke() {
+		// A stroke leaning to the right, moving one pixel for every two rows.
+		let stroke: Vec<_> = (4..24).map(|y| (8 + y / 2, y)).collect();
+		let image = image_with(IMAGE_SIZE, &stroke);
+
+		// The centre of mass of every row that has any ink.
+		let row_centers = |image: &GrayImage32| -> Vec<f32> {
+			(0..image.height())
+				.filter_map(|y| {
+					let row = image::imageops::crop_imm(image, 0, y, image.width(), 1).to_image();
+					center_of_mass(&row).map(|(x, _)| x)
+				})
+				.collect()
+		};
+		let spread = |centers: Vec<f32>| {
+			let min = centers.iter().copied().fold(f32::INFINITY, f32::min);
+			let max = centers.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+			max - min
+		};
+
+		assert!(spread(row_centers(&image)) > 8.);
+		assert!(spread(row_centers(&deskew(&image))) < 1.5);
+	}
+}
@@ -0,0 +1,182 @@
+use std::{
+	io::IsTerminal,
+	time::{Duration, Instant},
+};
+
+use indicatif::{HumanDuration, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::{info, log_enabled, Level};
+
+use crate::{
+	callback::{BatchMetrics, Callback, Control},
+	checkpoint::EpochMetrics,
+	error::Result,
+	network::Network,
+};
+
+const TEMPLATE: &str = "{prefix} [{wide_bar}] {pos}/{len} ({per_sec}, ETA {eta}) {msg}";
+/// How many images pass between updates of the running cost on the progress bar.
+const MESSAGE_EVERY: usize = 100;
+/// How often a line is logged when stdout isn't a terminal.
+const LOG_EVERY: Duration = Duration::from_secs(10);
+
+/// Progress through the images of a dataset.
+/// Draws a progress bar when stdout is a terminal, and logs a line every 10 seconds otherwise.
+/// Nothing is shown when the console logger doesn't log info messages.
+pub struct Progress {
+	label: String,
+	total: usize,
+	bar: Option<ProgressBar>,
+	started: Instant,
+	last_log: Instant,
+	position: usize,
+	/// The amount of images done since the progress was created.
+	done: usize,
+	cost_sum: f32,
+}
+
+impl Progress {
+	pub fn new(label: impl Into<String>, total: usize) -> Progress {
+		let label = label.into();
+		// Quiet runs don't show progress at all.
+		let shown = log_enabled!(target: "console", Level::Info);
+		let bar = (shown && std::io::stdout().is_terminal()).then(|| {
+			let bar =
+				ProgressBar::with_draw_target(Some(total as u64), ProgressDrawTarget::stdout());
+			let style = ProgressStyle::with_template(TEMPLATE)
+				.expect("The progress bar template is valid")
+				.progress_chars("=> ");
+			bar.set_style(style);
+			bar.set_prefix(label.clone());
+			bar
+		});
+
+		Progress {
+			label,
+			total,
+			bar,
+			started: Instant::now(),
+			last_log: Instant::now(),
+			position: 0,
+			done: 0,
+			cost_sum: 0.,
+		}
+	}
+
+	/// Start at `position`, e.g. when an interrupted dataset iteration is resumed.
+	pub fn start_at(&mut self, position: usize) {
+		self.position = position;
+		if let Some(bar) = &self.bar {
+			bar.set_position(position as u64);
+			bar.reset_eta();
+		}
+	}
+
+	/// Record that the image at `position - 1` is done, with the cost of the network on it.
+	/// The progress bar is cleared after the last image, so the summary can be logged.
+	pub fn update(&mut self, position: usize, cost: f32) {
+		self.position = position;
+		self.done += 1;
+		self.cost_sum += cost;
+
+		match &self.bar {
+			Some(bar) => {
+				bar.set_position(position as u64);
+				if position.is_multiple_of(MESSAGE_EVERY) || position == self.total {
+					bar.set_message(format!("avg. cost: {:.4}", self.avg_cost()));
+				}
+				if position >= self.total {
+					bar.finish_and_clear();
+				}
+			}
+			None => {
+				if self.last_log.elapsed() >= LOG_EVERY && position < self.total {
+					self.last_log = Instant::now();
+					self.log();
+				}
+			}
+		}
+	}
+
+	/// The average cost of the images done so far.
+	pub fn avg_cost(&self) -> f32 {
+		self.cost_sum / self.done.max(1) as f32
+	}
+
+	fn log(&self) {
+		let elapsed = self.started.elapsed().as_secs_f64();
+		let per_sec = self.done as f64 / elapsed.max(f64::EPSILON);
+		let remaining = (self.total - self.position) as f64 / per_sec.max(f64::EPSILON);
+
+		info!(
+			target: "console",
+			"{}: {}/{} ({:.1}%), {:.0} images/s, avg. cost: {:.4}, ETA {}",
+			self.label,
+			self.position,
+			self.total,
+			self.position as f32 / self.total.max(1) as f32 * 100.,
+			per_sec,
+			self.avg_cost(),
+			HumanDuration(Duration::from_secs_f64(remaining.min(u32::MAX as f64)))
+		);
+	}
+}
+
+impl Drop for Progress {
+	fn drop(&mut self) {
+		if let Some(bar) = &self.bar {
+			bar.finish_and_clear();
+		}
+	}
+}
+
+/// Shows the progress of every dataset iteration, and logs a summary after it.
+#[derive(Default)]
+pub struct ProgressCallback {
+	progress: Option<Progress>,
+}
+
+impl Callback for ProgressCallback {
+	fn on_epoch_start(&mut self, network: &mut Network, iteration: usize) -> Result<()> {
+		let total = network.dataset()?.training_labels.nrows();
+		self.progress = Some(Progress::new(format!("Iteration {}", iteration), total));
+		Ok(())
+	}
+
+	fn on_batch_end(&mut self, _: &mut Network, batch: &BatchMetrics) -> Result<()> {
+		if let Some(progress) = &mut self.progress {
+			// A resumed dataset iteration doesn't start at the first image.
+			if progress.done == 0 {
+				progress.start_at(batch.sample);
+			}
+			progress.update(batch.sample + 1, batch.cost);
+		}
+		Ok(())
+	}
+
+	fn on_epoch_end(&mut self, _: &mut Network, metrics: &EpochMetrics) -> Result<Control> {
+		self.progress = None;
+
+		let regularization = match metrics.regularization_cost {
+			cost if cost > 0. => format!(", regularization cost: {}", cost),
+			_ => String::new(),
+		};
+		let validation = match (metrics.validation_accuracy, metrics.validation_cost) {
+			(Some(accuracy), Some(cost)) => {
+				format!(", test accuracy: {}%, test avg. cost: {}", accuracy, cost)
+			}
+			_ => String::new(),
+		};
+		info!(
+			target: "console",
+			"Dataset iteration {} complete in {:.1}s – Accuracy: {}% ({}), avg. cost: {}{}{}",
+			metrics.iteration,
+			metrics.seconds,
+			metrics.accuracy,
+			metrics.correct,
+			metrics.avg_cost,
+			regularization,
+			validation
+		);
+		Ok(Control::Continue)
+	}
+}
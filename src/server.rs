@@ -0,0 +1,485 @@
+use std::{
+	future::Future,
+	net::SocketAddr,
+	path::{Path, PathBuf},
+	sync::{Arc, PoisonError, RwLock},
+	time::{Duration, SystemTime},
+};
+
+use axum::{
+	body::Bytes,
+	extract::State,
+	http::{header, HeaderMap, StatusCode},
+	response::{IntoResponse, Response},
+	routing::{get, post},
+	Json, Router,
+};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+	error::Result,
+	model::{load_path, ModelFile, ModelMetadata},
+	network::Network,
+	predict::{image_to_input, Prediction, PredictionReport},
+	preprocessing::Preprocessing,
+};
+
+/// How the inference server is run.
+#[derive(Clone, Debug)]
+pub struct ServerOptions {
+	/// The network to serve, reloaded when the file changes.
+	pub model: String,
+	pub address: SocketAddr,
+	/// The most requests fed forward together.
+	pub max_batch: usize,
+	/// How long the first request of a batch waits for others to join it.
+	pub batch_delay: Duration,
+	/// How often the model file is checked for changes.
+	pub reload_interval: Duration,
+}
+
+/// The network currently served, swapped out when the model file changes.
+struct LoadedModel {
+	network: Arc<Network>,
+	metadata: ModelMetadata,
+	modified: Option<SystemTime>,
+}
+
+/// A request waiting to be fed forward in a batch.
+struct Job {
+	input: Vec<f32>,
+	respond: oneshot::Sender<Result<Prediction, ApiError>>,
+}
+
+#[derive(Clone)]
+struct AppState {
+	model: Arc<RwLock<LoadedModel>>,
+	jobs: mpsc::Sender<Job>,
+}
+
+/// The pixels of an image, either as a bare array or in an object.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PixelsRequest {
+	Pixels(Vec<f32>),
+	Object { pixels: Vec<f32> },
+}
+
+/// An error returned to the client as `{"error": "..."}`.
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+	fn into_response(self) -> Response {
+		let body = serde_json::json!({ "error": self.1 });
+		(self.0, Json(body)).into_response()
+	}
+}
+
+impl LoadedModel {
+	fn read(path: &Path) -> Result<LoadedModel> {
+		let modified = std::fs::metadata(path)?.modified().ok();
+		let network = Network::from_model(ModelFile::read(path)?)?;
+
+		Ok(LoadedModel {
+			metadata: network.metadata(),
+			network: Arc::new(network),
+			modified,
+		})
+	}
+}
+
+/// Serve the network over HTTP until `shutdown` completes.
+pub async fn serve(
+	options: ServerOptions,
+	shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<()> {
+	let path = load_path(&options.model);
+	let model = Arc::new(RwLock::new(LoadedModel::read(&path)?));
+	let (jobs, receiver) = mpsc::channel(options.max_batch.max(1) * 4);
+
+	tokio::spawn(run_batches(
+		receiver,
+		model.clone(),
+		options.max_batch.max(1),
+		options.batch_delay,
+	));
+	tokio::spawn(watch_model(path, model.clone(), options.reload_interval));
+
+	let app = Router::new()
+		.route("/predict", post(predict))
+		.route("/model", get(model_metadata))
+		.route("/health", get(health))
+		.with_state(AppState { model, jobs });
+
+	let listener = tokio::net::TcpListener::bind(options.address).await?;
+	info!(target: "console", "Serving {} on http://{}", options.model, listener.local_addr()?);
+
+	axum::serve(listener, app)
+		.with_graceful_shutdown(shutdown)
+		.await?;
+
+	Ok(())
+}
+
+async fn predict(
+	State(state): State<AppState>,
+	headers: HeaderMap,
+	body: Bytes,
+) -> Result<Json<PredictionReport>, ApiError> {
+	let content_type = headers
+		.get(header::CONTENT_TYPE)
+		.and_then(|value| value.to_str().ok())
+		.unwrap_or_default();
+
+	let input = if content_type.starts_with("image/") {
+		let image = image::load_from_memory(&body)
+			.map_err(|err| ApiError(StatusCode::BAD_REQUEST, format!("invalid image: {}", err)))?;
+		image_to_input(&image, &Preprocessing::default())
+	} else {
+		let request: PixelsRequest = serde_json::from_slice(&body)
+			.map_err(|err| ApiError(StatusCode::BAD_REQUEST, format!("invalid JSON: {}", err)))?;
+		match request {
+			PixelsRequest::Pixels(pixels) | PixelsRequest::Object { pixels } => pixels,
+		}
+	};
+
+	let input_size = state
+		.model
+		.read()
+		.unwrap_or_else(PoisonError::into_inner)
+		.metadata
+		.input_size;
+	if input.len() != input_size {
+		return Err(ApiError(
+			StatusCode::BAD_REQUEST,
+			format!("expected {} pixels, got {}", input_size, input.len()),
+		));
+	}
+
+	let (respond, response) = oneshot::channel();
+	let unavailable = || {
+		ApiError(
+			StatusCode::SERVICE_UNAVAILABLE,
+			"server is shutting down".to_string(),
+		)
+	};
+	state
+		.jobs
+		.send(Job { input, respond })
+		.await
+		.map_err(|_| unavailable())?;
+	let prediction = response.await.map_err(|_| unavailable())??;
+
+	Ok(Json(prediction.into()))
+}
+
+async fn model_metadata(State(state): State<AppState>) -> Json<ModelMetadata> {
+	Json(
+		state
+			.model
+			.read()
+			.unwrap_or_else(PoisonError::into_inner)
+			.metadata
+			.clone(),
+	)
+}
+
+async fn health() -> Json<serde_json::Value> {
+	Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Feed the queued requests forward in batches.
+/// Requests that arrive within `delay` of the first one share its forward pass.
+async fn run_batches(
+	mut receiver: mpsc::Receiver<Job>,
+	model: Arc<RwLock<LoadedModel>>,
+	max_batch: usize,
+	delay: Duration,
+) {
+	while let Some(first) = receiver.recv().await {
+		let mut jobs = vec![first];
+		let deadline = tokio::time::Instant::now() + delay;
+
+		while jobs.len() < max_batch {
+			match tokio::time::timeout_at(deadline, receiver.recv()).await {
+				Ok(Some(job)) => jobs.push(job),
+				Ok(None) | Err(_) => break,
+			}
+		}
+
+		let (network, input_size) = {
+			let model = model.read().unwrap_or_else(PoisonError::into_inner);
+			(model.network.clone(), model.metadata.input_size)
+		};
+
+		// The network may have been reloaded with another shape since the requests were checked.
+		let (jobs, mismatched): (Vec<_>, Vec<_>) = jobs
+			.into_iter()
+			.partition(|job| job.input.len() == input_size);
+		for job in mismatched {
+			let _ = job.respond.send(Err(ApiError(
+				StatusCode::CONFLICT,
+				format!(
+					"the network was reloaded and now expects {} pixels, got {}",
+					input_size,
+					job.input.len()
+				),
+			)));
+		}
+		if jobs.is_empty() {
+			continue;
+		}
+
+		debug!("Feeding a batch of {} requests forward", jobs.len());
+		let (inputs, responders): (Vec<_>, Vec<_>) =
+			jobs.into_iter().map(|job| (job.input, job.respond)).unzip();
+
+		let outputs = match tokio::task::spawn_blocking(move || network.infer_batch(&inputs)).await
+		{
+			Ok(outputs) => outputs,
+			Err(err) => {
+				warn!("Feeding a batch forward failed: {}", err);
+				for respond in responders {
+					let _ = respond.send(Err(ApiError(
+						StatusCode::INTERNAL_SERVER_ERROR,
+						"could not feed the image forward".to_string(),
+					)));
+				}
+				continue;
+			}
+		};
+
+		for (respond, output) in responders.into_iter().zip(outputs) {
+			// The client may have disconnected, which is fine.
+			let _ = respond.send(Ok(Prediction::from_outputs(&output)));
+		}
+	}
+}
+
+/// Reload the model whenever the file is modified.
+/// The previous model keeps being served if the new file can't be loaded.
+async fn watch_model(path: PathBuf, model: Arc<RwLock<LoadedModel>>, interval: Duration) {
+	let mut interval = tokio::time::interval(interval);
+
+	loop {
+		interval.tick().await;
+
+		let modified = std::fs::metadata(&path)
+			.and_then(|metadata| metadata.modified())
+			.ok();
+		let loaded = model
+			.read()
+			.unwrap_or_else(PoisonError::into_inner)
+			.modified;
+		if modified.is_none() || modified == loaded {
+			continue;
+		}
+
+		let reload_path = path.clone();
+		match tokio::task::spawn_blocking(move || LoadedModel::read(&reload_path)).await {
+			Ok(Ok(loaded)) => {
+				info!(target: "console", "Reloaded network from {}", path.display());
+				*model.write().unwrap_or_else(PoisonError::into_inner) = loaded;
+			}
+			Ok(Err(err)) => {
+				warn!(target: "console", "Could not reload network from {}: {}", path.display(), err);
+				// Don't retry until the file changes again.
+				model
+					.write()
+					.unwrap_or_else(PoisonError::into_inner)
+					.modified = modified;
+			}
+			Err(err) => warn!("Reloading the network failed: {}", err),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use image::{DynamicImage, GrayImage, ImageOutputFormat};
+
+	use super::*;
+
+	fn loaded(shape: &[usize]) -> Arc<RwLock<LoadedModel>> {
+		let network = Network::new(0.1, shape).unwrap();
+		Arc::new(RwLock::new(LoadedModel {
+			metadata: network.metadata(),
+			network: Arc::new(network),
+			modified: None,
+		}))
+	}
+
+	/// The state of a server for `model`, with its batches fed forward in the background.
+	fn app_state(model: Arc<RwLock<LoadedModel>>) -> AppState {
+		let (jobs, receiver) = mpsc::channel(16);
+		tokio::spawn(run_batches(
+			receiver,
+			model.clone(),
+			4,
+			Duration::from_millis(10),
+		));
+		AppState { model, jobs }
+	}
+
+	fn headers(content_type: &str) -> HeaderMap {
+		let mut headers = HeaderMap::new();
+		headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+		headers
+	}
+
+	async fn post(
+		state: &AppState,
+		content_type: &str,
+		body: Vec<u8>,
+	) -> Result<PredictionReport, ApiError> {
+		predict(State(state.clone()), headers(content_type), body.into())
+			.await
+			.map(|Json(report)| report)
+	}
+
+	fn status(result: Result<PredictionReport, ApiError>) -> StatusCode {
+		match result {
+			Ok(_) => StatusCode::OK,
+			Err(ApiError(status, _)) => status,
+		}
+	}
+
+	#[tokio::test]
+	async fn batches_answer_every_job() {
+		let (jobs, receiver) = mpsc::channel(8);
+		tokio::spawn(run_batches(
+			receiver,
+			loaded(&[4, 3, 2]),
+			8,
+			Duration::from_millis(50),
+		));
+
+		let mut responses = Vec::new();
+		for input in [vec![0.5; 4], vec![0.5; 3], vec![1.; 4], Vec::new()] {
+			let (respond, response) = oneshot::channel();
+			jobs.send(Job { input, respond }).await.unwrap();
+			responses.push(response);
+		}
+
+		let mut results = Vec::new();
+		for response in responses {
+			results.push(response.await.unwrap());
+		}
+
+		for index in [0, 2] {
+			let prediction = results[index].as_ref().ok().unwrap();
+			assert_eq!(prediction.probabilities.len(), 2);
+		}
+		for index in [1, 3] {
+			let ApiError(status, message) = results[index].as_ref().err().unwrap();
+			assert_eq!(*status, StatusCode::CONFLICT);
+			assert!(message.contains("expects 4 pixels"), "{}", message);
+		}
+	}
+
+	#[tokio::test]
+	async fn predicts_pixels_and_images() {
+		let state = app_state(loaded(&[784, 4, 10]));
+
+		let array = serde_json::to_vec(&vec![0.5; 784]).unwrap();
+		let report = post(&state, "application/json", array).await.ok().unwrap();
+		assert_eq!(report.probabilities.len(), 10);
+		assert_eq!(report.top.len(), 3);
+
+		let object = serde_json::to_vec(&serde_json::json!({ "pixels": vec![0.5; 784] })).unwrap();
+		assert_eq!(
+			status(post(&state, "application/json", object).await),
+			StatusCode::OK
+		);
+
+		let mut png = Cursor::new(Vec::new());
+		DynamicImage::ImageLuma8(GrayImage::new(28, 28))
+			.write_to(&mut png, ImageOutputFormat::Png)
+			.unwrap();
+		let report = post(&state, "image/png", png.into_inner())
+			.await
+			.ok()
+			.unwrap();
+		assert_eq!(report.probabilities.len(), 10);
+	}
+
+	#[tokio::test]
+	async fn rejects_invalid_requests() {
+		let state = app_state(loaded(&[784, 4, 10]));
+
+		let wrong_size = serde_json::to_vec(&vec![0.5; 10]).unwrap();
+		let result = post(&state, "application/json", wrong_size).await;
+		let ApiError(code, message) = result.err().unwrap();
+		assert_eq!(code, StatusCode::BAD_REQUEST);
+		assert_eq!(message, "expected 784 pixels, got 10");
+
+		let invalid = post(&state, "application/json", b"{\"pixels\":".to_vec()).await;
+		assert_eq!(status(invalid), StatusCode::BAD_REQUEST);
+
+		let not_an_image = post(&state, "image/png", b"not a png".to_vec()).await;
+		assert_eq!(status(not_an_image), StatusCode::BAD_REQUEST);
+	}
+
+	#[tokio::test]
+	async fn reports_the_model_and_health() {
+		let state = app_state(loaded(&[784, 4, 10]));
+
+		let Json(metadata) = model_metadata(State(state)).await;
+		assert_eq!(metadata.input_size, 784);
+		assert_eq!(metadata.shape, [784, 4, 10]);
+
+		let Json(health) = health().await;
+		assert_eq!(health, serde_json::json!({ "status": "ok" }));
+	}
+
+	#[tokio::test]
+	async fn reloads_the_model_when_the_file_changes() {
+		let path =
+			std::env::temp_dir().join(format!("mnist-ai-server-{}.json", std::process::id()));
+		Network::new(0.1, &[4, 3, 2])
+			.unwrap()
+			.to_model()
+			.write(&path)
+			.unwrap();
+		let model = Arc::new(RwLock::new(LoadedModel::read(&path).unwrap()));
+		let watcher = tokio::spawn(watch_model(
+			path.clone(),
+			model.clone(),
+			Duration::from_millis(10),
+		));
+
+		Network::new(0.1, &[6, 3, 2])
+			.unwrap()
+			.to_model()
+			.write(&path)
+			.unwrap();
+		// Make sure the change is noticed, even if the file system's timestamps are coarse.
+		std::fs::File::options()
+			.write(true)
+			.open(&path)
+			.unwrap()
+			.set_modified(SystemTime::now() + Duration::from_secs(10))
+			.unwrap();
+
+		let mut input_size = 4;
+		for _ in 0..200 {
+			tokio::time::sleep(Duration::from_millis(10)).await;
+			input_size = model
+				.read()
+				.unwrap_or_else(PoisonError::into_inner)
+				.metadata
+				.input_size;
+			if input_size != 4 {
+				break;
+			}
+		}
+
+		watcher.abort();
+		let _ = std::fs::remove_file(&path);
+		assert_eq!(input_size, 6);
+	}
+}
@@ -0,0 +1,238 @@
+use std::io::{self, BufRead, Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+	network::Network,
+	predict::{image_to_input, PredictionReport},
+	preprocessing::Preprocessing,
+};
+
+/// A line read by `predict --stdio`, with either the pixels or a base64 encoded PNG.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StdioRequest {
+	/// Copied to the response, so predictions can be matched to their requests.
+	#[serde(default)]
+	pub id: Value,
+	pub pixels: Option<Vec<f32>>,
+	pub png: Option<String>,
+}
+
+/// A line written by `predict --stdio`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum StdioResponse {
+	Prediction {
+		id: Value,
+		#[serde(flatten)]
+		report: PredictionReport,
+	},
+	Error {
+		id: Value,
+		error: String,
+	},
+}
+
+/// Answer every JSON line of `input` with a JSON line on `output`, until `input` ends.
+/// Invalid lines are answered with an error instead of stopping.
+pub fn run(
+	network: &Network,
+	preprocessing: &Preprocessing,
+	input: impl BufRead,
+	mut output: impl Write,
+) -> io::Result<()> {
+	for line in input.lines() {
+		let line = line?;
+		if line.trim().is_empty() {
+			continue;
+		}
+
+		let response = respond(network, preprocessing, &line);
+		serde_json::to_writer(&mut output, &response)?;
+		writeln!(output)?;
+		// Flush every line, so the other end of a pipe doesn't wait for a full buffer.
+		output.flush()?;
+	}
+
+	Ok(())
+}
+
+/// The response to a single line.
+pub fn respond(network: &Network, preprocessing: &Preprocessing, line: &str) -> StdioResponse {
+	let request: StdioRequest = match serde_json::from_str(line) {
+		Ok(request) => request,
+		Err(err) => {
+			// The id is still copied when only the rest of the request is invalid.
+			let id = serde_json::from_str::<Value>(line)
+				.ok()
+				.and_then(|value| value.get("id").cloned())
+				.unwrap_or_default();
+			return StdioResponse::Error {
+				id,
+				error: format!("invalid request: {}", err),
+			};
+		}
+	};
+
+	match request_input(&request, preprocessing, network.shape()[0]) {
+		Ok(input) => StdioResponse::Prediction {
+			id: request.id,
+			report: network.predict(&input).into(),
+		},
+		Err(error) => StdioResponse::Error {
+			id: request.id,
+			error,
+		},
+	}
+}
+
+/// The input for the network described by a request.
+fn request_input(
+	request: &StdioRequest,
+	preprocessing: &Preprocessing,
+	input_size: usize,
+) -> Result<Vec<f32>, String> {
+	let input = match (&request.pixels, &request.png) {
+		(Some(pixels), None) => pixels.clone(),
+		(None, Some(png)) => {
+			let bytes = STANDARD
+				.decode(png)
+				.map_err(|err| format!("invalid base64: {}", err))?;
+			let image = image::load_from_memory_with_format(&bytes, image::ImageFormat::Png)
+				.map_err(|err| format!("invalid PNG: {}", err))?;
+			image_to_input(&image, preprocessing)
+		}
+		_ => return Err("expected exactly one of `pixels` and `png`".to_string()),
+	};
+
+	if input.len() != input_size {
+		return Err(format!(
+			"expected {} pixels, got {}",
+			input_size,
+			input.len()
+		));
+	}
+
+	Ok(input)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use image::{DynamicImage, GrayImage, ImageOutputFormat, Luma};
+	use serde_json::json;
+
+	use super::*;
+
+	fn network() -> Network {
+		Network::new(0.1, &[784, 4, 10]).unwrap()
+	}
+
+	fn respond_to(request: Value) -> Value {
+		let response = respond(&network(), &Preprocessing::default(), &request.to_string());
+		serde_json::to_value(response).unwrap()
+	}
+
+	fn png() -> String {
+		let mut image = GrayImage::new(28, 28);
+		for x in 10..18 {
+			for y in 6..22 {
+				image.put_pixel(x, y, Luma([255]));
+			}
+		}
+
+		let mut bytes = Cursor::new(Vec::new());
+		DynamicImage::ImageLuma8(image)
+			.write_to(&mut bytes, ImageOutputFormat::Png)
+			.unwrap();
+		STANDARD.encode(bytes.into_inner())
+	}
+
+	fn assert_prediction(response: &Value, id: Value) {
+		assert_eq!(response["id"], id);
+		assert!(response.get("error").is_none(), "{}", response);
+		assert!(response["digit"].as_u64().unwrap() < 10);
+		assert_eq!(response["probabilities"].as_array().unwrap().len(), 10);
+		assert_eq!(response["top"].as_array().unwrap().len(), 3);
+	}
+
+	fn assert_error(response: &Value, id: Value, message: &str) {
+		assert_eq!(response["id"], id);
+		let error = response["error"].as_str().unwrap();
+		assert!(error.contains(message), "{}", error);
+	}
+
+	#[test]
+	fn predicts_pixels() {
+		let response = respond_to(json!({ "id": 1, "pixels": vec![0.5; 784] }));
+		assert_prediction(&response, json!(1));
+	}
+
+	#[test]
+	fn predicts_pngs() {
+		let response = respond_to(json!({ "id": "a", "png": png() }));
+		assert_prediction(&response, json!("a"));
+
+		let response = respond_to(json!({ "id": "b", "png": "not base64!" }));
+		assert_error(&response, json!("b"), "invalid base64");
+	}
+
+	#[test]
+	fn rejects_the_wrong_input_size() {
+		let response = respond_to(json!({ "id": 2, "pixels": [0.5, 0.5] }));
+		assert_error(&response, json!(2), "expected 784 pixels, got 2");
+	}
+
+	#[test]
+	fn needs_exactly_one_input() {
+		let both = respond_to(json!({ "id": 3, "pixels": vec![0.; 784], "png": png() }));
+		assert_error(&both, json!(3), "exactly one");
+
+		let neither = respond_to(json!({ "id": 4 }));
+		assert_error(&neither, json!(4), "exactly one");
+	}
+
+	#[test]
+	fn invalid_requests_keep_their_id() {
+		let response = respond_to(json!({ "id": 5, "pixels": "none" }));
+		assert_error(&response, json!(5), "invalid request");
+
+		let response = respond_to(json!({ "id": 6, "pixels": [], "size": 3 }));
+		assert_error(&response, json!(6), "unknown field");
+
+		let response = respond(&network(), &Preprocessing::default(), "{\"id\": 7,");
+		let response = serde_json::to_value(response).unwrap();
+		assert_error(&response, Value::Null, "invalid request");
+	}
+
+	#[test]
+	fn answers_every_line() {
+		let input = format!(
+			"{}\n\n{}\nnot json\n",
+			json!({ "id": 1, "pixels": vec![0.; 784] }),
+			json!({ "id": 2, "pixels": [] })
+		);
+		let mut output = Vec::new();
+		run(
+			&network(),
+			&Preprocessing::default(),
+			input.as_bytes(),
+			&mut output,
+		)
+		.unwrap();
+
+		let responses: Vec<Value> = String::from_utf8(output)
+			.unwrap()
+			.lines()
+			.map(|line| serde_json::from_str(line).unwrap())
+			.collect();
+		assert_eq!(responses.len(), 3);
+		assert_prediction(&responses[0], json!(1));
+		assert_error(&responses[1], json!(2), "expected 784 pixels");
+		assert_error(&responses[2], Value::Null, "invalid request");
+	}
+}
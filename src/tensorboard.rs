@@ -0,0 +1,546 @@
+//! Writes TensorBoard event files, so training runs can be viewed with a stock TensorBoard.
+//!
+//! Event files are TFRecord files of `Event` protocol buffers. Only the few messages
+//! needed for scalars, histograms and images are encoded, by hand.
+
+use std::{
+	fs::{self, File},
+	io::{BufWriter, Cursor, Write},
+	path::{Path, PathBuf},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use image::{ImageBuffer, ImageOutputFormat, Luma};
+use ndarray::Axis;
+
+use crate::{
+	callback::{Callback, Control},
+	checkpoint::EpochMetrics,
+	error::Result,
+	gradient::Gradients,
+	network::{Evaluation, Network},
+};
+
+/// The amount of buckets of every histogram.
+const HISTOGRAM_BUCKETS: usize = 30;
+/// The most misclassified test images written after every dataset iteration.
+const MISCLASSIFIED_IMAGES: usize = 8;
+/// The amount of training images the gradient histograms are averaged over.
+const GRADIENT_SAMPLES: usize = 64;
+
+/// Writes scalars, histograms of the weights and gradients of every layer and misclassified
+/// test images to an event file after every dataset iteration.
+pub struct TensorBoardWriter {
+	file: BufWriter<File>,
+	path: PathBuf,
+	/// The indices of the misclassified test images of the last evaluation.
+	misclassified: Vec<usize>,
+}
+
+impl TensorBoardWriter {
+	/// Create a new event file in the folder `log_dir`, which is created if it doesn't exist.
+	pub fn create(log_dir: impl AsRef<Path>) -> Result<TensorBoardWriter> {
+		let log_dir = log_dir.as_ref();
+		fs::create_dir_all(log_dir)?;
+
+		let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "localhost".to_string());
+		let name = format!(
+			"events.out.tfevents.{}.{}.{}",
+			wall_time() as u64,
+			host,
+			std::process::id()
+		);
+		let path = log_dir.join(name);
+
+		let mut writer = TensorBoardWriter {
+			file: BufWriter::new(File::create(&path)?),
+			path,
+			misclassified: Vec::new(),
+		};
+		// Every event file starts with its version.
+		let mut event = Vec::new();
+		put_double(&mut event, 1, wall_time());
+		put_bytes(&mut event, 3, b"brain.Event:2");
+		writer.write_record(&event)?;
+		writer.file.flush()?;
+
+		Ok(writer)
+	}
+
+	/// The event file written to.
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Write a single value.
+	pub fn scalar(&mut self, tag: &str, step: usize, value: f32) -> Result<()> {
+		let mut summary_value = Vec::new();
+		put_bytes(&mut summary_value, 1, tag.as_bytes());
+		put_float(&mut summary_value, 2, value);
+		self.write_summary(step, &summary_value)
+	}
+
+	/// Write a histogram of `values`.
+	pub fn histogram(&mut self, tag: &str, step: usize, values: &[f32]) -> Result<()> {
+		let mut summary_value = Vec::new();
+		put_bytes(&mut summary_value, 1, tag.as_bytes());
+		put_bytes(&mut summary_value, 5, &histogram(values));
+		self.write_summary(step, &summary_value)
+	}
+
+	/// Write a grayscale image with pixels between 0 and 1.
+	pub fn image(
+		&mut self,
+		tag: &str,
+		step: usize,
+		width: usize,
+		height: usize,
+		pixels: &[f32],
+	) -> Result<()> {
+		let image = ImageBuffer::from_fn(width as u32, height as u32, |x, y| {
+			let pixel = pixels[y as usize * width + x as usize];
+			Luma([(pixel.clamp(0., 1.) * 255.) as u8])
+		});
+		let mut png = Cursor::new(Vec::new());
+		image.write_to(&mut png, ImageOutputFormat::Png)?;
+
+		let mut encoded = Vec::new();
+		put_varint_field(&mut encoded, 1, height as u64);
+		put_varint_field(&mut encoded, 2, width as u64);
+		// Grayscale.
+		put_varint_field(&mut encoded, 3, 1);
+		put_bytes(&mut encoded, 4, png.get_ref());
+
+		let mut summary_value = Vec::new();
+		put_bytes(&mut summary_value, 1, tag.as_bytes());
+		put_bytes(&mut summary_value, 4, &encoded);
+		self.write_summary(step, &summary_value)
+	}
+
+	/// Write an event with a summary of a single value.
+	fn write_summary(&mut self, step: usize, summary_value: &[u8]) -> Result<()> {
+		let mut summary = Vec::new();
+		put_bytes(&mut summary, 1, summary_value);
+
+		let mut event = Vec::new();
+		put_double(&mut event, 1, wall_time());
+		put_varint_field(&mut event, 2, step as u64);
+		put_bytes(&mut event, 5, &summary);
+		self.write_record(&event)
+	}
+
+	/// Write a TFRecord: the length, its checksum, the data and its checksum.
+	fn write_record(&mut self, data: &[u8]) -> Result<()> {
+		let length = (data.len() as u64).to_le_bytes();
+		self.file.write_all(&length)?;
+		self.file.write_all(&masked_crc32c(&length).to_le_bytes())?;
+		self.file.write_all(data)?;
+		self.file.write_all(&masked_crc32c(data).to_le_bytes())?;
+		Ok(())
+	}
+
+	/// Write the misclassified test images of the last evaluation,
+	/// as `misclassified/0` and onwards. Datasets of points instead of images are skipped.
+	fn write_misclassified(&mut self, network: &Network, step: usize) -> Result<()> {
+		let misclassified = std::mem::take(&mut self.misclassified);
+		let Some(dataset) = &network.dataset else {
+			return Ok(());
+		};
+		let (_, height, width) = dataset.test_images.dim();
+		if height < 2 {
+			return Ok(());
+		}
+
+		for (number, index) in misclassified.into_iter().enumerate() {
+			let image = dataset.test_images.index_axis(Axis(0), index);
+			let pixels: Vec<f32> = image.iter().copied().collect();
+			self.image(
+				&format!("misclassified/{}", number),
+				step,
+				width,
+				height,
+				&pixels,
+			)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Writes every dataset iteration, including the test metrics.
+impl Callback for TensorBoardWriter {
+	fn evaluates(&self) -> bool {
+		true
+	}
+
+	fn on_evaluate(&mut self, _: &mut Network, evaluation: &Evaluation) -> Result<()> {
+		self.misclassified = (0..evaluation.len())
+			.filter(|index| !evaluation.is_correct(*index))
+			.take(MISCLASSIFIED_IMAGES)
+			.collect();
+		Ok(())
+	}
+
+	fn on_epoch_end(&mut self, network: &mut Network, metrics: &EpochMetrics) -> Result<Control> {
+		let step = metrics.iteration;
+		self.scalar("train/cost", step, metrics.avg_cost)?;
+		self.scalar("train/accuracy", step, metrics.accuracy)?;
+		self.scalar(
+			"train/regularization_cost",
+			step,
+			metrics.regularization_cost,
+		)?;
+		if let Some(cost) = metrics.validation_cost {
+			self.scalar("validation/cost", step, cost)?;
+		}
+		if let Some(accuracy) = metrics.validation_accuracy {
+			self.scalar("validation/accuracy", step, accuracy)?;
+		}
+		self.scalar("learning_rate", step, metrics.learning_rate)?;
+		self.scalar("gradient_norm", step, metrics.gradient_norm)?;
+		self.scalar("seconds", step, metrics.seconds)?;
+
+		let gradients = sample_gradients(network);
+		let layers = network.layers();
+		for (index, neurons) in layers.iter().enumerate() {
+			let name = if index == layers.len() - 1 {
+				"output".to_string()
+			} else {
+				format!("layer_{}", index + 1)
+			};
+
+			let weights: Vec<f32> = neurons
+				.iter()
+				.flat_map(|neuron| neuron.weights.iter().copied())
+				.collect();
+			let biases: Vec<f32> = neurons.iter().map(|neuron| neuron.bias).collect();
+			self.histogram(&format!("{}/weights", name), step, &weights)?;
+			self.histogram(&format!("{}/biases", name), step, &biases)?;
+
+			if let Some(gradients) = &gradients {
+				let weights: Vec<f32> = gradients.weights[index].concat();
+				self.histogram(&format!("{}/weight_gradients", name), step, &weights)?;
+				self.histogram(
+					&format!("{}/bias_gradients", name),
+					step,
+					&gradients.biases[index],
+				)?;
+			}
+		}
+
+		self.write_misclassified(network, step)?;
+		self.file.flush()?;
+		Ok(Control::Continue)
+	}
+}
+
+/// The gradients averaged over the first training images, if the network has a dataset.
+fn sample_gradients(network: &Network) -> Option<Gradients> {
+	let dataset = network.dataset.as_ref()?;
+	let samples = dataset.training_labels.nrows().min(GRADIENT_SAMPLES);
+	if samples == 0 {
+		return None;
+	}
+
+	let mut gradients = Gradients::zeros(&network.layers());
+	for index in 0..samples {
+		let input: Vec<f32> = dataset
+			.training_images
+			.index_axis(Axis(0), index)
+			.iter()
+			.copied()
+			.collect();
+		let label = dataset.training_labels[[index, 0]];
+		gradients.add(&network.gradients(&input, label));
+	}
+	gradients.scale(1. / samples as f32);
+
+	Some(gradients)
+}
+
+/// Encode a `HistogramProto` of `values` with evenly sized buckets.
+fn histogram(values: &[f32]) -> Vec<u8> {
+	let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+	let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+	let (min, max) = if values.is_empty() {
+		(0., 0.)
+	} else {
+		(min, max)
+	};
+
+	let buckets = if max > min { HISTOGRAM_BUCKETS } else { 1 };
+	let width = (max - min) / buckets as f32;
+	let mut counts = vec![0.; buckets];
+	for value in values {
+		let bucket = if width > 0. {
+			(((value - min) / width) as usize).min(buckets - 1)
+		} else {
+			0
+		};
+		counts[bucket] += 1.;
+	}
+	// The upper edge of every bucket.
+	let limits: Vec<f64> = (1..=buckets)
+		.map(|bucket| (min + width * bucket as f32) as f64)
+		.collect();
+
+	let mut encoded = Vec::new();
+	put_double(&mut encoded, 1, min as f64);
+	put_double(&mut encoded, 2, max as f64);
+	put_double(&mut encoded, 3, values.len() as f64);
+	put_double(
+		&mut encoded,
+		4,
+		values.iter().map(|value| *value as f64).sum(),
+	);
+	put_double(
+		&mut encoded,
+		5,
+		values.iter().map(|value| (*value as f64).powi(2)).sum(),
+	);
+	put_packed_doubles(&mut encoded, 6, &limits);
+	put_packed_doubles(&mut encoded, 7, &counts);
+	encoded
+}
+
+fn wall_time() -> f64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0., |time| time.as_secs_f64())
+}
+
+// Protocol buffer wire types.
+const VARINT: u64 = 0;
+const FIXED64: u64 = 1;
+const LENGTH_DELIMITED: u64 = 2;
+const FIXED32: u64 = 5;
+
+fn put_varint(buffer: &mut Vec<u8>, mut value: u64) {
+	while value >= 0x80 {
+		buffer.push(value as u8 | 0x80);
+		value >>= 7;
+	}
+	buffer.push(value as u8);
+}
+
+fn put_key(buffer: &mut Vec<u8>, field: u64, wire_type: u64) {
+	put_varint(buffer, field << 3 | wire_type);
+}
+
+fn put_varint_field(buffer: &mut Vec<u8>, field: u64, value: u64) {
+	put_key(buffer, field, VARINT);
+	put_varint(buffer, value);
+}
+
+fn put_double(buffer: &mut Vec<u8>, field: u64, value: f64) {
+	put_key(buffer, field, FIXED64);
+	buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_float(buffer: &mut Vec<u8>, field: u64, value: f32) {
+	put_key(buffer, field, FIXED32);
+	buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_bytes(buffer: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+	put_key(buffer, field, LENGTH_DELIMITED);
+	put_varint(buffer, bytes.len() as u64);
+	buffer.extend_from_slice(bytes);
+}
+
+fn put_packed_doubles(buffer: &mut Vec<u8>, field: u64, values: &[f64]) {
+	let bytes: Vec<u8> = values
+		.iter()
+		.flat_map(|value| value.to_le_bytes())
+		.collect();
+	put_bytes(buffer, field, &bytes);
+}
+
+/// The CRC-32C checksum of TFRecords, masked so checksums of checksums stay useful.
+fn masked_crc32c(data: &[u8]) -> u32 {
+	let crc = crc32c(data);
+	crc.rotate_right(15).wrapping_add(0xa282_ead8)
+}
+
+/// CRC-32C, with the Castagnoli polynomial.
+fn crc32c(data: &[u8]) -> u32 {
+	const POLYNOMIAL: u32 = 0x82f6_3b78;
+
+	let mut crc = !0u32;
+	for byte in data {
+		crc ^= *byte as u32;
+		for _ in 0..8 {
+			crc = if crc & 1 == 1 {
+				(crc >> 1) ^ POLYNOMIAL
+			} else {
+				crc >> 1
+			};
+		}
+	}
+	!crc
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A decoded protocol buffer field.
+	#[derive(Debug, PartialEq)]
+	enum Field<'a> {
+		Varint(u64),
+		Fixed64([u8; 8]),
+		Bytes(&'a [u8]),
+		Fixed32([u8; 4]),
+	}
+
+	fn read_varint(data: &[u8], position: &mut usize) -> u64 {
+		let mut value = 0;
+		let mut shift = 0;
+		loop {
+			let byte = data[*position];
+			*position += 1;
+			value |= ((byte & 0x7f) as u64) << shift;
+			if byte < 0x80 {
+				return value;
+			}
+			shift += 7;
+		}
+	}
+
+	/// Decode every field of a message, by field number.
+	fn decode(data: &[u8]) -> Vec<(u64, Field<'_>)> {
+		let mut fields = Vec::new();
+		let mut position = 0;
+		while position < data.len() {
+			let key = read_varint(data, &mut position);
+			let field = match key & 7 {
+				VARINT => Field::Varint(read_varint(data, &mut position)),
+				FIXED64 => {
+					position += 8;
+					Field::Fixed64(data[position - 8..position].try_into().unwrap())
+				}
+				LENGTH_DELIMITED => {
+					let length = read_varint(data, &mut position) as usize;
+					position += length;
+					Field::Bytes(&data[position - length..position])
+				}
+				FIXED32 => {
+					position += 4;
+					Field::Fixed32(data[position - 4..position].try_into().unwrap())
+				}
+				wire_type => panic!("unknown wire type {}", wire_type),
+			};
+			fields.push((key >> 3, field));
+		}
+		fields
+	}
+
+	fn field<'a>(fields: &'a [(u64, Field<'a>)], number: u64) -> &'a Field<'a> {
+		&fields
+			.iter()
+			.find(|(field, _)| *field == number)
+			.unwrap_or_else(|| panic!("missing field {}", number))
+			.1
+	}
+
+	fn bytes<'a>(fields: &[(u64, Field<'a>)], number: u64) -> &'a [u8] {
+		match fields.iter().find(|(field, _)| *field == number) {
+			Some((_, Field::Bytes(bytes))) => bytes,
+			field => panic!("expected bytes for field {}, got {:?}", number, field),
+		}
+	}
+
+	fn double(fields: &[(u64, Field)], number: u64) -> f64 {
+		match field(fields, number) {
+			Field::Fixed64(bytes) => f64::from_le_bytes(*bytes),
+			field => panic!("expected a double for field {}, got {:?}", number, field),
+		}
+	}
+
+	/// Split a TFRecord file into its records, checking both checksums of each.
+	fn read_records(contents: &[u8]) -> Vec<&[u8]> {
+		let mut records = Vec::new();
+		let mut rest = contents;
+		while !rest.is_empty() {
+			let (length, after) = rest.split_at(8);
+			let (length_crc, after) = after.split_at(4);
+			assert_eq!(length_crc, masked_crc32c(length).to_le_bytes());
+
+			let length = u64::from_le_bytes(length.try_into().unwrap()) as usize;
+			let (data, after) = after.split_at(length);
+			let (data_crc, after) = after.split_at(4);
+			assert_eq!(data_crc, masked_crc32c(data).to_le_bytes());
+
+			records.push(data);
+			rest = after;
+		}
+		records
+	}
+
+	#[test]
+	fn writes_readable_event_files() {
+		let log_dir =
+			std::env::temp_dir().join(format!("mnist-ai-tensorboard-{}", std::process::id()));
+		let mut writer = TensorBoardWriter::create(&log_dir).unwrap();
+		writer.scalar("train/cost", 3, 0.25).unwrap();
+		writer
+			.histogram("output/weights", 4, &[-1., 0., 0.5, 1.])
+			.unwrap();
+		writer.image("digit", 5, 2, 2, &[0., 1., 0.5, 0.]).unwrap();
+		writer.file.flush().unwrap();
+
+		let contents = fs::read(writer.path()).unwrap();
+		let _ = fs::remove_dir_all(&log_dir);
+		let records = read_records(&contents);
+		assert_eq!(records.len(), 4);
+
+		let version = decode(records[0]);
+		assert_eq!(bytes(&version, 3), b"brain.Event:2");
+
+		let event = decode(records[1]);
+		assert_eq!(field(&event, 2), &Field::Varint(3));
+		let value = decode(bytes(&decode(bytes(&event, 5)), 1));
+		assert_eq!(bytes(&value, 1), b"train/cost");
+		assert_eq!(field(&value, 2), &Field::Fixed32(0.25f32.to_le_bytes()));
+
+		let event = decode(records[2]);
+		assert_eq!(field(&event, 2), &Field::Varint(4));
+		let value = decode(bytes(&decode(bytes(&event, 5)), 1));
+		assert_eq!(bytes(&value, 1), b"output/weights");
+		let histogram = decode(bytes(&value, 5));
+		assert_eq!(double(&histogram, 1), -1.);
+		assert_eq!(double(&histogram, 2), 1.);
+		assert_eq!(double(&histogram, 3), 4.);
+		assert_eq!(double(&histogram, 4), 0.5);
+		assert_eq!(double(&histogram, 5), 2.25);
+		let counts: Vec<f64> = bytes(&histogram, 7)
+			.chunks(8)
+			.map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+			.collect();
+		assert_eq!(counts.len(), HISTOGRAM_BUCKETS);
+		assert_eq!(counts.iter().sum::<f64>(), 4.);
+		assert_eq!(bytes(&histogram, 6).len(), HISTOGRAM_BUCKETS * 8);
+
+		let event = decode(records[3]);
+		let value = decode(bytes(&decode(bytes(&event, 5)), 1));
+		let image = decode(bytes(&value, 4));
+		assert_eq!(field(&image, 1), &Field::Varint(2));
+		assert_eq!(field(&image, 2), &Field::Varint(2));
+		let png = image::load_from_memory(bytes(&image, 4))
+			.unwrap()
+			.to_luma8();
+		assert_eq!(png.into_raw(), [0, 255, 127, 0]);
+	}
+
+	#[test]
+	fn crc32c_check_value() {
+		assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+	}
+
+	#[test]
+	fn varint() {
+		let mut buffer = Vec::new();
+		put_varint(&mut buffer, 300);
+		assert_eq!(buffer, [0xac, 0x02]);
+	}
+}
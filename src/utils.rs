@@ -1,7 +1,13 @@
+use std::{
+	fs::{self, File},
+	io::{self, Write},
+	path::Path,
+};
+
 use image::{self, ImageBuffer, Luma};
 use ndarray::Array3;
 
-use crate::neuron::Neuron;
+use crate::{error::Result, neuron::Neuron};
 
 #[allow(dead_code)]
 pub fn get_label_desc(label: impl Into<u8>) -> String {
@@ -23,16 +29,16 @@ pub fn get_label_desc(label: impl Into<u8>) -> String {
 }
 
 #[allow(dead_code)]
-pub fn save_image(image_num: usize, image_data: &Array3<f32>) {
+pub fn save_image(image_num: usize, image_data: &Array3<f32>) -> Result<()> {
 	ImageBuffer::from_fn(28, 28, |x, y| {
 		Luma([(image_data[[image_num, y as usize, x as usize]] * 256.0) as u8])
 	})
-	.save(format!("images/{image_num}"))
-	.unwrap();
+	.save(format!("images/{image_num}"))?;
+	Ok(())
 }
 
 #[allow(dead_code)]
-pub fn save_neuron_as_image(neuron: &Neuron, name: impl Into<String>) {
+pub fn save_neuron_as_image(neuron: &Neuron, name: impl Into<String>) -> Result<()> {
 	let name_into = name.into();
 	let dimension = f32::sqrt(neuron.weights.len() as f32) as u32;
 	// TODO: Fix this naming and code
@@ -45,13 +51,185 @@ pub fn save_neuron_as_image(neuron: &Neuron, name: impl Into<String>) {
 		let weight = neuron_2d[x as usize][y as usize];
 		Luma([(weight * 256.0) as u8])
 	})
-	.save(&format!("images/{name_into}"))
-	.unwrap();
+	.save(format!("images/{name_into}"))?;
+	Ok(())
+}
+
+/// Write `contents` to `path` without ever leaving a partially written file behind.
+/// The contents are written to a temporary file next to `path`, which is then
+/// renamed over it. Missing parent folders are created.
+pub fn write_atomic(path: impl AsRef<Path>, contents: &[u8]) -> io::Result<()> {
+	let path = path.as_ref();
+	let parent = path
+		.parent()
+		.filter(|parent| !parent.as_os_str().is_empty());
+	if let Some(parent) = parent {
+		fs::create_dir_all(parent)?;
+	}
+
+	let file_name = path
+		.file_name()
+		.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+	let mut temp_name = file_name.to_os_string();
+	temp_name.push(format!(".{}.tmp", std::process::id()));
+	let temp_path = path.with_file_name(temp_name);
+
+	let result = File::create(&temp_path).and_then(|mut file| {
+		file.write_all(contents)?;
+		file.sync_all()
+	});
+
+	match result.and_then(|_| fs::rename(&temp_path, path)) {
+		Ok(()) => Ok(()),
+		Err(err) => {
+			let _ = fs::remove_file(&temp_path);
+			Err(err)
+		}
+	}
 }
 
 #[allow(dead_code)]
 pub mod activation_functions {
+	use serde::{Deserialize, Serialize};
+
+	/// The activation functions a network can use.
+	#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+	#[serde(rename_all = "lowercase")]
+	pub enum Activation {
+		#[default]
+		Sigmoid,
+		Tanh,
+		Relu,
+	}
+
+	impl Activation {
+		pub fn apply(self, x: f32) -> f32 {
+			match self {
+				Activation::Sigmoid => sigmoid(x),
+				Activation::Tanh => x.tanh(),
+				Activation::Relu => x.max(0.),
+			}
+		}
+
+		/// The derivative of the activation function,
+		/// calculated from the output of the function instead of its input.
+		pub fn derivative(self, activation: f32) -> f32 {
+			match self {
+				Activation::Sigmoid => activation * (1. - activation),
+				Activation::Tanh => 1. - activation * activation,
+				Activation::Relu => {
+					if activation > 0. {
+						1.
+					} else {
+						0.
+					}
+				}
+			}
+		}
+	}
+
 	pub fn sigmoid(x: f32) -> f32 {
 		1.0 / (1.0 + (-x).exp())
 	}
 }
+
+#[allow(dead_code)]
+pub mod loss_functions {
+	use serde::{Deserialize, Serialize};
+
+	/// Keeps the logarithm in the cross entropy finite for saturated neurons.
+	const EPSILON: f32 = 1e-7;
+
+	/// The loss functions a network can be trained with.
+	/// The output layer always uses the sigmoid activation function.
+	#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+	#[serde(rename_all = "snake_case")]
+	pub enum Loss {
+		#[default]
+		CrossEntropy,
+		Quadratic,
+	}
+
+	impl Loss {
+		/// The loss of a single output neuron.
+		pub fn cost(self, activation: f32, desired: f32) -> f32 {
+			match self {
+				Loss::CrossEntropy => {
+					let activation = activation.clamp(EPSILON, 1. - EPSILON);
+					-(desired * activation.ln() + (1. - desired) * (1. - activation).ln())
+				}
+				Loss::Quadratic => 0.5 * (activation - desired).powi(2),
+			}
+		}
+
+		/// The error signal of a sigmoid output neuron, the negative gradient
+		/// of the loss with respect to the weighted input of the neuron.
+		pub fn error_signal(self, activation: f32, desired: f32) -> f32 {
+			match self {
+				Loss::CrossEntropy => desired - activation,
+				Loss::Quadratic => (desired - activation) * activation * (1. - activation),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::PathBuf;
+
+	use super::*;
+
+	/// An empty temporary folder for a single test.
+	fn temp_dir(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("mnist-ai-{}-{}", name, std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		dir
+	}
+
+	fn entries(dir: &Path) -> Vec<String> {
+		let mut entries: Vec<String> = fs::read_dir(dir)
+			.unwrap()
+			.map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+			.collect();
+		entries.sort();
+		entries
+	}
+
+	#[test]
+	fn write_atomic_creates_the_parent_folders() {
+		let dir = temp_dir("write-nested");
+		let path = dir.join("a").join("b").join("model.json");
+
+		write_atomic(&path, b"first").unwrap();
+		write_atomic(&path, b"second").unwrap();
+
+		assert_eq!(fs::read(&path).unwrap(), b"second");
+		assert_eq!(entries(path.parent().unwrap()), ["model.json"]);
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn failed_writes_leave_the_target_alone() {
+		let dir = temp_dir("write-failed");
+		let path = dir.join("model.json");
+		fs::write(&path, b"old").unwrap();
+
+		// The temporary file can't be created where a folder is in the way.
+		let temp_path = dir.join(format!("model.json.{}.tmp", std::process::id()));
+		fs::create_dir(&temp_path).unwrap();
+		assert!(write_atomic(&path, b"new").is_err());
+		assert_eq!(fs::read(&path).unwrap(), b"old");
+		fs::remove_dir(&temp_path).unwrap();
+
+		// The temporary file can't be renamed over a folder, and is removed again.
+		let folder = dir.join("folder");
+		fs::create_dir(&folder).unwrap();
+		fs::write(folder.join("kept"), b"").unwrap();
+		assert!(write_atomic(&folder, b"new").is_err());
+		assert_eq!(entries(&folder), ["kept"]);
+
+		assert_eq!(entries(&dir), ["folder", "model.json"]);
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
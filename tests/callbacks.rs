@@ -0,0 +1,147 @@
+//! Callbacks implemented outside the crate, trained on a synthetic dataset.
+
+mod common;
+
+use std::{cell::RefCell, rc::Rc};
+
+use mnist_ai_rust::{
+	callback::{BatchMetrics, Callback, Control},
+	checkpoint::{EpochMetrics, TrainingState},
+	config::ExperimentConfig,
+	dataset::{SYNTHETIC_TEST, SYNTHETIC_TRAINING},
+	error::Result,
+	network::{Evaluation, Network},
+};
+
+#[derive(Debug, Default)]
+struct Calls {
+	epoch_starts: usize,
+	batches: usize,
+	evaluations: usize,
+	learning_rates: Vec<f32>,
+}
+
+/// Counts every hook, halves the learning rate before every dataset iteration
+/// and stops once the test cost hasn't improved for `patience` iterations.
+struct Recorder {
+	calls: Rc<RefCell<Calls>>,
+	patience: usize,
+	best_cost: Option<f32>,
+	waited: usize,
+}
+
+impl Recorder {
+	fn new(patience: usize) -> (Recorder, Rc<RefCell<Calls>>) {
+		let calls = Rc::new(RefCell::new(Calls::default()));
+		let recorder = Recorder {
+			calls: calls.clone(),
+			patience,
+			best_cost: None,
+			waited: 0,
+		};
+		(recorder, calls)
+	}
+}
+
+impl Callback for Recorder {
+	fn on_epoch_start(&mut self, network: &mut Network, _: usize) -> Result<()> {
+		self.calls.borrow_mut().epoch_starts += 1;
+		network.learning_rate *= 0.5;
+		Ok(())
+	}
+
+	fn on_batch_end(&mut self, _: &mut Network, batch: &BatchMetrics) -> Result<()> {
+		let mut calls = self.calls.borrow_mut();
+		assert_eq!(batch.sample, calls.batches % SYNTHETIC_TRAINING);
+		calls.batches += 1;
+		Ok(())
+	}
+
+	fn evaluates(&self) -> bool {
+		true
+	}
+
+	fn on_evaluate(&mut self, _: &mut Network, evaluation: &Evaluation) -> Result<()> {
+		assert_eq!(evaluation.len(), SYNTHETIC_TEST);
+		self.calls.borrow_mut().evaluations += 1;
+		Ok(())
+	}
+
+	fn on_epoch_end(&mut self, network: &mut Network, metrics: &EpochMetrics) -> Result<Control> {
+		assert_eq!(metrics.learning_rate, network.learning_rate);
+		self.calls
+			.borrow_mut()
+			.learning_rates
+			.push(metrics.learning_rate);
+
+		let cost = metrics.validation_cost.expect("The network was evaluated");
+		if self.best_cost.is_none_or(|best| cost < best) {
+			self.best_cost = Some(cost);
+			self.waited = 0;
+		} else {
+			self.waited += 1;
+		}
+
+		Ok(if self.waited >= self.patience {
+			Control::Stop
+		} else {
+			Control::Continue
+		})
+	}
+}
+
+fn train(iterations: usize, recorder: Recorder) -> (bool, TrainingState) {
+	let config = ExperimentConfig {
+		hidden: vec![4],
+		iterations,
+		shuffle: false,
+		..common::blobs_config()
+	};
+
+	let mut callbacks: Vec<Box<dyn Callback>> = vec![Box::new(recorder)];
+	let (_, state, finished) = common::train_with(&config, &mut callbacks);
+	(finished, state)
+}
+
+#[test]
+fn every_hook_is_called() {
+	let (recorder, calls) = Recorder::new(usize::MAX);
+	let (finished, state) = train(3, recorder);
+
+	assert!(finished);
+	// The first dataset iteration doesn't back propagate, so there is one extra.
+	assert_eq!(state.history.len(), 4);
+	assert!(state
+		.history
+		.iter()
+		.all(|epoch| epoch.validation_cost.is_some()));
+
+	let calls = calls.borrow();
+	assert_eq!(calls.epoch_starts, 4);
+	assert_eq!(calls.batches, 4 * SYNTHETIC_TRAINING);
+	assert_eq!(calls.evaluations, 4);
+}
+
+#[test]
+fn callbacks_change_the_learning_rate() {
+	let (recorder, calls) = Recorder::new(usize::MAX);
+	let (_, state) = train(2, recorder);
+
+	// The schedule keeps the learning rate at 0.1, which is halved before every iteration.
+	assert_eq!(calls.borrow().learning_rates, [0.05; 3]);
+	assert!(state
+		.history
+		.iter()
+		.all(|epoch| epoch.learning_rate == 0.05));
+}
+
+#[test]
+fn callbacks_stop_training_early() {
+	let (recorder, calls) = Recorder::new(0);
+	let (finished, state) = train(100, recorder);
+
+	assert!(finished);
+	assert!(state.is_finished());
+	assert_eq!(state.history.len(), 1);
+	assert_eq!(calls.borrow().epoch_starts, 1);
+}
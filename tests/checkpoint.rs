@@ -0,0 +1,117 @@
+//! Training that is interrupted, written to a checkpoint and resumed.
+
+mod common;
+
+use std::sync::{
+	atomic::{AtomicBool, Ordering},
+	Arc,
+};
+
+use mnist_ai_rust::{
+	callback::{BatchMetrics, Callback},
+	checkpoint::{Checkpoint, CheckpointOptions, EpochMetrics, TrainingState},
+	config::ExperimentConfig,
+	error::Result,
+	network::Network,
+};
+
+/// Sets `stop` after the batch at `sample` of dataset iteration `iteration`.
+struct StopAt {
+	iteration: usize,
+	sample: usize,
+	stop: Arc<AtomicBool>,
+}
+
+impl Callback for StopAt {
+	fn on_batch_end(&mut self, _: &mut Network, batch: &BatchMetrics) -> Result<()> {
+		if batch.iteration == self.iteration && batch.sample == self.sample {
+			self.stop.store(true, Ordering::Relaxed);
+		}
+		Ok(())
+	}
+}
+
+fn config() -> ExperimentConfig {
+	ExperimentConfig {
+		iterations: 4,
+		seed: Some(7),
+		..common::blobs_config()
+	}
+}
+
+fn weights(network: &Network) -> Vec<(Vec<f32>, f32)> {
+	network
+		.layers()
+		.iter()
+		.flat_map(|neurons| neurons.iter())
+		.map(|neuron| (neuron.weights.clone(), neuron.bias))
+		.collect()
+}
+
+/// The history without the timings, which differ between runs.
+fn history(state: &TrainingState) -> Vec<EpochMetrics> {
+	state
+		.history
+		.iter()
+		.map(|epoch| EpochMetrics {
+			seconds: 0.,
+			..epoch.clone()
+		})
+		.collect()
+}
+
+#[test]
+fn resuming_continues_exactly_where_training_stopped() {
+	let config = config();
+	let (uninterrupted, expected) = common::train(&config);
+
+	let path =
+		std::env::temp_dir().join(format!("mnist-ai-checkpoint-{}.json", std::process::id()));
+	let options = CheckpointOptions {
+		path: path.clone(),
+		every: None,
+	};
+
+	// Stop halfway through the third dataset iteration.
+	let stop = Arc::new(AtomicBool::new(false));
+	let mut callbacks: Vec<Box<dyn Callback>> = vec![Box::new(StopAt {
+		iteration: 2,
+		sample: 37,
+		stop: stop.clone(),
+	})];
+	let mut network = common::network(&config);
+	let mut state = TrainingState::from_config(&config);
+	let finished = network
+		.train_from(&mut state, Some(&options), &mut callbacks, &stop)
+		.unwrap();
+	assert!(!finished);
+
+	let checkpoint = Checkpoint::read(&path).unwrap();
+	std::fs::remove_file(&path).unwrap();
+	assert_eq!(
+		(checkpoint.state.iteration, checkpoint.state.sample),
+		(2, 38)
+	);
+	assert_eq!(checkpoint.state.history.len(), 2);
+
+	let (mut resumed, mut state) = Network::resume(checkpoint).unwrap();
+	assert!(resumed
+		.train_from(&mut state, None, &mut [], &AtomicBool::new(false))
+		.unwrap());
+
+	assert_eq!(weights(&resumed), weights(&uninterrupted));
+	assert_eq!(
+		(state.iteration, state.sample, state.correct, state.cost_sum),
+		(
+			expected.iteration,
+			expected.sample,
+			expected.correct,
+			expected.cost_sum
+		)
+	);
+	assert_eq!(state.gradient_norm_sum, expected.gradient_norm_sum);
+	assert_eq!(state.order, expected.order);
+	assert_eq!(state.rng, expected.rng);
+	assert_eq!(state.base_learning_rate, expected.base_learning_rate);
+	assert_eq!(history(&state), history(&expected));
+}
@@ -0,0 +1,56 @@
+//! Fixtures shared by the integration tests.
+// Every test file compiles this module on its own and uses only some of it.
+#![allow(dead_code)]
+
+use std::sync::atomic::AtomicBool;
+
+use mnist_ai_rust::{
+	callback::Callback,
+	checkpoint::TrainingState,
+	config::{Dataset, ExperimentConfig},
+	network::Network,
+};
+
+/// A seeded run of a small network on the Blobs dataset,
+/// tests change only the fields they are about.
+pub fn blobs_config() -> ExperimentConfig {
+	ExperimentConfig {
+		dataset: Dataset::Blobs,
+		hidden: vec![8],
+		iterations: 5,
+		shuffle: true,
+		seed: Some(1),
+		..ExperimentConfig::default()
+	}
+}
+
+/// A new network for `config`, sized for its dataset.
+pub fn network(config: &ExperimentConfig) -> Network {
+	Network::from_config(
+		config,
+		config.dataset.input_size(),
+		config.dataset.classes(),
+	)
+	.unwrap()
+}
+
+/// Train a new network for `config` with `callbacks`, returning the network, its final state
+/// and whether every dataset iteration completed.
+pub fn train_with(
+	config: &ExperimentConfig,
+	callbacks: &mut [Box<dyn Callback>],
+) -> (Network, TrainingState, bool) {
+	let mut network = network(config);
+	let mut state = TrainingState::from_config(config);
+	let finished = network
+		.train_from(&mut state, None, callbacks, &AtomicBool::new(false))
+		.unwrap();
+	(network, state, finished)
+}
+
+/// Train a new network for `config` until every dataset iteration is done.
+pub fn train(config: &ExperimentConfig) -> (Network, TrainingState) {
+	let (network, state, finished) = train_with(config, &mut []);
+	assert!(finished);
+	(network, state)
+}
@@ -0,0 +1,99 @@
+//! Dropout while training, and networks with dropout saved and loaded.
+
+mod common;
+
+use mnist_ai_rust::{
+	config::ExperimentConfig,
+	model::{ModelFile, ModelFormat},
+	network::Network,
+};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+fn config(dropout: Vec<f32>) -> ExperimentConfig {
+	ExperimentConfig {
+		hidden: vec![16, 8],
+		dropout,
+		iterations: 10,
+		seed: Some(3),
+		..common::blobs_config()
+	}
+}
+
+fn train(config: &ExperimentConfig) -> Network {
+	common::train(config).0
+}
+
+#[test]
+fn config_rates_apply_to_every_hidden_layer() {
+	let network = common::network(&config(vec![0.25]));
+	assert_eq!(network.dropout(), [0.25, 0.25]);
+
+	let network = common::network(&config(vec![0.5, 0.1]));
+	assert_eq!(network.dropout(), [0.5, 0.1]);
+
+	assert!(Network::from_config(&config(vec![0.5, 0.1, 0.1]), 2, 4).is_err());
+	assert!(config(vec![1.]).validate().is_err());
+	assert!(config(vec![-0.1]).validate().is_err());
+}
+
+#[test]
+fn dropout_only_happens_while_training() {
+	let mut network = common::network(&config(vec![0.5]));
+	let input = [0.3, -0.7];
+	let expected = network.infer(&input);
+
+	network.feed_forward_dropout(input.to_vec(), &mut ChaCha8Rng::seed_from_u64(0));
+	for layer in &network.activation_layers {
+		assert_eq!(layer.mask.len(), layer.neurons.len());
+		assert!(layer.mask.iter().all(|scale| *scale == 0. || *scale == 2.));
+		assert!(layer.mask.contains(&0.));
+	}
+
+	network.feed_forward(input.to_vec());
+	assert!(network
+		.activation_layers
+		.iter()
+		.all(|layer| layer.mask.is_empty()));
+	assert_eq!(network.output_layer.activations(), expected);
+	assert_eq!(network.infer(&input), expected);
+}
+
+#[test]
+fn training_with_dropout_is_reproducible() {
+	let first = train(&config(vec![0.2]));
+	let second = train(&config(vec![0.2]));
+	let without = train(&config(Vec::new()));
+
+	let weights = |network: &Network| network.to_model().to_json().unwrap();
+	assert_eq!(weights(&first), weights(&second));
+	assert_ne!(weights(&first), weights(&without));
+
+	let mut network = first;
+	assert!(network.evaluate().unwrap().accuracy() >= 90.);
+}
+
+#[test]
+fn dropout_is_saved_with_the_model() {
+	let network = common::network(&config(vec![0.5, 0.1]));
+
+	for file in ["model.json", "model.bin", "model.f16.bin.gz"] {
+		let encoded = network
+			.to_model()
+			.encode(ModelFormat::from_path(file))
+			.unwrap();
+		let model = ModelFile::decode(&encoded).unwrap();
+		assert_eq!(model.metadata.dropout, [0.5, 0.1], "{}", file);
+		assert_eq!(Network::from_model(model).unwrap().dropout(), [0.5, 0.1]);
+	}
+
+	// Networks without dropout don't store it.
+	let network = common::network(&config(Vec::new()));
+	let json: serde_json::Value =
+		serde_json::from_str(&network.to_model().to_json().unwrap()).unwrap();
+	assert!(json["metadata"].get("dropout").is_none());
+
+	let mut model = network.to_model();
+	model.metadata.dropout = vec![0.5];
+	assert!(Network::from_model(model).is_err());
+}
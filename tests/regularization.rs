@@ -0,0 +1,148 @@
+//! Weight penalties and max-norm constraints while training.
+
+mod common;
+
+use mnist_ai_rust::{
+	checkpoint::TrainingState,
+	config::{ExperimentConfig, Regularization},
+	network::Network,
+};
+
+fn config(regularization: Regularization) -> ExperimentConfig {
+	ExperimentConfig {
+		regularization,
+		seed: Some(2),
+		..common::blobs_config()
+	}
+}
+
+fn train(regularization: Regularization) -> (Network, TrainingState) {
+	common::train(&config(regularization))
+}
+
+fn weights(network: &Network) -> Vec<f32> {
+	network
+		.layers()
+		.iter()
+		.flat_map(|neurons| neurons.iter())
+		.flat_map(|neuron| neuron.weights.iter().copied())
+		.collect()
+}
+
+fn norm(weights: &[f32], power: i32) -> f32 {
+	weights.iter().map(|weight| weight.abs().powi(power)).sum()
+}
+
+#[test]
+fn penalties_shrink_the_weights() {
+	let (plain, _) = train(Regularization::default());
+	let (l1, _) = train(Regularization {
+		l1: 1e-3,
+		..Regularization::default()
+	});
+	let (l2, _) = train(Regularization {
+		l2: 1e-2,
+		..Regularization::default()
+	});
+
+	assert!(norm(&weights(&l1), 1) < norm(&weights(&plain), 1));
+	assert!(norm(&weights(&l2), 2) < norm(&weights(&plain), 2));
+}
+
+#[test]
+fn decoupled_decay_takes_the_same_sgd_step() {
+	let l2 = Regularization {
+		l2: 1e-2,
+		..Regularization::default()
+	};
+	let (coupled, _) = train(l2);
+	let (decoupled, _) = train(Regularization {
+		decoupled: true,
+		..l2
+	});
+
+	for (coupled, decoupled) in weights(&coupled).iter().zip(weights(&decoupled)) {
+		assert!(
+			(coupled - decoupled).abs() < 1e-4,
+			"{} vs. {}",
+			coupled,
+			decoupled
+		);
+	}
+}
+
+#[test]
+fn l1_leaves_zero_weights_alone() {
+	let regularization = Regularization {
+		l1: 0.5,
+		..Regularization::default()
+	};
+	let mut weights = [0., 1., -1.];
+	regularization.step(&mut weights, &[0.; 3], 0.1);
+	assert_eq!(weights, [0., 0.95, -0.95]);
+}
+
+#[test]
+fn max_norm_limits_every_neuron() {
+	let (network, _) = train(Regularization {
+		max_norm: Some(0.5),
+		..Regularization::default()
+	});
+
+	for neuron in network.layers().iter().flat_map(|neurons| neurons.iter()) {
+		assert!(norm(&neuron.weights, 2).sqrt() <= 0.5 + 1e-5);
+	}
+}
+
+#[test]
+fn the_penalty_is_reported_separately() {
+	let (network, state) = train(Regularization {
+		l1: 1e-3,
+		l2: 1e-2,
+		..Regularization::default()
+	});
+
+	let last = state.history.last().unwrap();
+	assert!(last.regularization_cost > 0.);
+	assert_eq!(last.regularization_cost, network.regularization_cost());
+
+	let (_, state) = train(Regularization::default());
+	assert!(state
+		.history
+		.iter()
+		.all(|epoch| epoch.regularization_cost == 0.));
+}
+
+#[test]
+fn regularization_is_saved_with_the_config() {
+	let regularization = Regularization {
+		l2: 1e-3,
+		max_norm: Some(3.),
+		..Regularization::default()
+	};
+	let network = common::network(&config(regularization));
+	let loaded = Network::from_model(network.to_model()).unwrap();
+	assert_eq!(loaded.regularization, regularization);
+}
+
+#[test]
+fn invalid_regularization_is_rejected() {
+	let invalid = [
+		Regularization {
+			l1: -1.,
+			..Regularization::default()
+		},
+		Regularization {
+			l2: f32::NAN,
+			..Regularization::default()
+		},
+		Regularization {
+			max_norm: Some(0.),
+			..Regularization::default()
+		},
+	];
+
+	for regularization in invalid {
+		assert!(config(regularization).validate().is_err());
+	}
+}
@@ -0,0 +1,95 @@
+//! Train and test networks on the synthetic datasets, which need no downloads.
+
+mod common;
+
+use mnist_ai_rust::{
+	config::{Dataset, ExperimentConfig, Optimizer},
+	network::{Evaluation, Network},
+};
+
+/// Train a new network on `dataset`, returning the average cost of the first and
+/// the last dataset iteration, and the evaluation on the test dataset.
+fn train(
+	dataset: Dataset,
+	hidden: &[usize],
+	iterations: usize,
+	learning_rate: f32,
+) -> (f32, f32, Evaluation) {
+	let config = ExperimentConfig {
+		dataset,
+		hidden: hidden.to_vec(),
+		optimizer: Optimizer::Sgd { learning_rate },
+		iterations,
+		..common::blobs_config()
+	};
+	let (mut network, state) = common::train(&config);
+
+	let first = state.history.first().unwrap().avg_cost;
+	let last = state.history.last().unwrap().avg_cost;
+	(first, last, network.evaluate().unwrap())
+}
+
+fn assert_learns(
+	dataset: Dataset,
+	(first, last, evaluation): (f32, f32, Evaluation),
+	accuracy: f32,
+) {
+	assert!(
+		last < first * 0.8,
+		"{:?}: cost went from {} to {}",
+		dataset,
+		first,
+		last
+	);
+	assert!(
+		evaluation.accuracy() >= accuracy,
+		"{:?}: test accuracy {}%",
+		dataset,
+		evaluation.accuracy()
+	);
+}
+
+#[test]
+fn xor() {
+	let result = train(Dataset::Xor, &[8], 30, 0.5);
+	assert_learns(Dataset::Xor, result, 90.);
+}
+
+#[test]
+fn spirals() {
+	let result = train(Dataset::Spirals, &[32], 20, 1.);
+	assert_learns(Dataset::Spirals, result, 90.);
+}
+
+#[test]
+fn blobs() {
+	let result = train(Dataset::Blobs, &[8], 10, 0.5);
+	assert_learns(Dataset::Blobs, result, 95.);
+}
+
+#[test]
+fn digits() {
+	let result = train(Dataset::Digits, &[16], 3, 0.1);
+	assert_learns(Dataset::Digits, result, 90.);
+}
+
+#[test]
+fn test_reports_accuracy_of_the_untrained_network() {
+	let mut network = common::network(&common::blobs_config());
+
+	network.test().unwrap();
+	let accuracy = network.accuracy.unwrap();
+	assert!((0. ..=100.).contains(&accuracy));
+}
+
+#[test]
+fn mismatched_dataset_is_an_error() {
+	let config = ExperimentConfig {
+		dataset: Dataset::Xor,
+		..ExperimentConfig::default()
+	};
+	let mut network = Network::from_config(&config, 784, 10).unwrap();
+
+	assert!(network.evaluate().is_err());
+	assert!(network.train(1).is_err());
+}